@@ -0,0 +1,81 @@
+//! Compares the vectorized CPU kernels of `program_simd`, with every
+//! instruction set of the CPU like SSE2 and AVX2, with the scalar ones of
+//! `program_cpu`. Run with `cargo bench --no-default-features`. The README
+//! lists the results.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use sdftool::program_cpu;
+use sdftool::program_simd::Simd;
+
+const SIZE: usize = 1024;
+
+/// A grid of discs with thin bars between them, giving edges all over the
+/// image.
+fn shapes(size: usize) -> Vec<u8> {
+    (0 .. size * size)
+        .map(|i| {
+            let (x, y) = ((i % size) as i32, (i / size) as i32);
+            let (dx, dy) = (x % 96 - 48, y % 96 - 48);
+            let inside = dx * dx + dy * dy < 30 * 30 || x % 96 < 3;
+            if inside { 255 } else { 0 }
+        })
+        .collect()
+}
+
+fn grayscale(c: &mut Criterion) {
+    let rgba: Vec<u8> = (0 .. SIZE * SIZE * 4).map(|i| i as u8).collect();
+    let mut dst = vec![0; SIZE * SIZE];
+
+    let mut group = c.benchmark_group("rgba_to_grayscale");
+    group.throughput(Throughput::Elements((SIZE * SIZE) as u64));
+    group.bench_function("scalar", |b|
+        b.iter(|| program_cpu::rgba_to_grayscale(&rgba, &mut dst, 4)));
+    for simd in Simd::supported() {
+        group.bench_function(simd.name(), |b|
+            b.iter(|| simd.rgba_to_grayscale(&rgba, &mut dst, 4)));
+    }
+    group.finish();
+}
+
+fn edge_detect(c: &mut Criterion) {
+    let image = shapes(SIZE);
+    let mut edge = vec![0; SIZE * SIZE];
+
+    let mut group = c.benchmark_group("edge_detect");
+    group.throughput(Throughput::Elements((SIZE * SIZE) as u64));
+    group.bench_function("scalar", |b|
+        b.iter(|| program_cpu::edge_detect(&image, &mut edge, SIZE, SIZE, 1)));
+    for simd in Simd::supported() {
+        group.bench_function(simd.name(), |b|
+            b.iter(|| simd.edge_detect(&image, &mut edge, SIZE, SIZE, 1)));
+    }
+    group.finish();
+}
+
+fn sdf_generate(c: &mut Criterion) {
+    let image = shapes(SIZE);
+    let mut edge = vec![0; SIZE * SIZE];
+    program_cpu::edge_detect(&image, &mut edge, SIZE, SIZE, 1);
+
+    let (stride, search_radius) = (4, 32);
+    let sdf_size = SIZE / stride;
+    let mut sdf = vec![0.0; sdf_size * sdf_size];
+
+    let mut group = c.benchmark_group("sdf_generate");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((sdf_size * sdf_size) as u64));
+    group.bench_function("scalar", |b|
+        b.iter(||
+            program_cpu::sdf_generate(
+                &edge, &mut sdf, SIZE, SIZE, sdf_size, sdf_size, stride, search_radius, 1)));
+    for simd in Simd::supported() {
+        group.bench_function(simd.name(), |b|
+            b.iter(||
+                simd.sdf_generate(
+                    &edge, &mut sdf, SIZE, SIZE, sdf_size, sdf_size, stride, search_radius, 1)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, grayscale, edge_detect, sdf_generate);
+criterion_main!(benches);
@@ -0,0 +1,159 @@
+//! Compares handing the characters of a font out through `WorkQueue` with
+//! the `Mutex<Option<char>>` and `Condvar` loops of `font` it replaced, for
+//! charsets the size of the schinese-1/2/3 tables. Run with
+//! `cargo bench --no-default-features --bench work_queue`, the README has
+//! the results.
+
+use std::hint::black_box;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use sdftool::work_queue::WorkQueue;
+
+const WORKERS: usize = 4;
+
+/// Stands in for rendering a small glyph.
+fn render(ch: char) -> u32 {
+    (0..2000).fold(ch as u32, |x, i| black_box(x.wrapping_mul(31).wrapping_add(i)))
+}
+
+/// State shared by the workers of the old scheduler, `FontJob` before
+/// `WorkQueue`, with a counter for the progress bar.
+struct TaskSlot {
+    progress_bar: Mutex<usize>,
+    task: Mutex<Option<char>>,
+    cvar: Condvar,
+    run: AtomicBool
+}
+
+/// `font_worker` before `WorkQueue`, copied as is but for rendering.
+fn task_slot_worker(job: &TaskSlot) {
+    loop {
+        {   // Test break condition
+            if !job.run.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+
+        let generate_basic_task = 
+        {   // Get Next Task
+            let mut task = job.task.lock().unwrap();
+            let ch = task.take();
+            job.cvar.notify_one();
+            ch
+        };
+
+        if let Some(ch) = generate_basic_task {   
+            // Do task
+            black_box(render(ch));
+        }
+    }
+}
+
+/// The scheduler of `sdftool font` before `WorkQueue`: the producer loop
+/// of `font` hands the characters to the workers one at a time.
+fn task_slot(tasks: usize) {
+    let job = Arc::new(TaskSlot {
+        progress_bar: Mutex::new(0),
+        task: Mutex::new(None),
+        cvar: Condvar::new(),
+        run: AtomicBool::new(true)
+    });
+
+    let workers: Vec<_> =
+        (0..WORKERS)
+            .map(|_| {
+                let job = job.clone();
+                thread::spawn(move || task_slot_worker(&job))
+            })
+            .collect();
+
+    for i in (0..tasks as u32).filter_map(char::from_u32) {
+        let mut task = job.task.lock().unwrap();
+        while task.is_some() && job.run.load(Ordering::Acquire) {
+            task = job.cvar.wait(task).unwrap();
+        }
+
+        if !job.run.load(Ordering::Acquire) {
+            break;
+        }
+
+        *task = Some(i);
+
+        drop(task);
+
+        *job.progress_bar.lock().unwrap() += 1;
+    }
+
+    loop {
+        let mut task = job.task.lock().unwrap();
+        if task.is_some() && job.run.load(Ordering::Acquire) {
+            task = job.cvar.wait(task).unwrap();
+            
+            if task.is_none() {
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+
+    job.run.store(false, Ordering::Release);
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+/// The scheduler of `sdftool font`, with the progress bar advanced per
+/// batch like `FontJob::advance`.
+fn work_queue(tasks: usize) {
+    let queue = Arc::new(WorkQueue::new(16));
+    let progress_bar = Arc::new(Mutex::new(0));
+
+    let workers: Vec<_> =
+        (0..WORKERS)
+            .map(|_| {
+                let queue = queue.clone();
+                let progress_bar = progress_bar.clone();
+                thread::spawn(move || {
+                    let mut batch = Vec::new();
+                    while queue.pop_batch(&mut batch) {
+                        let count = batch.len();
+                        for ch in batch.drain(..) {
+                            black_box(render(ch));
+                        }
+
+                        *progress_bar.lock().unwrap() += count;
+                    }
+                })
+            })
+            .collect();
+
+    queue.extend((0..tasks as u32).filter_map(char::from_u32));
+    queue.close();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+fn schedulers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("font_scheduler");
+    group.sample_size(10);
+
+    for &(charset, tasks) in &[("schinese-1", 3500), ("schinese-2", 3000), ("schinese-3", 1605)] {
+        group.throughput(Throughput::Elements(tasks as u64));
+        group.bench_with_input(BenchmarkId::new("task_slot", charset), &tasks, |b, &tasks|
+            b.iter(|| task_slot(tasks)));
+        group.bench_with_input(BenchmarkId::new("work_queue", charset), &tasks, |b, &tasks|
+            b.iter(|| work_queue(tasks)));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, schedulers);
+criterion_main!(benches);
@@ -1,10 +1,14 @@
-#![feature(path_try_exists)]
-use std::{fs::*, process::Command};
+use std::{fs::*, path::Path, process::Command};
 
 fn setup_opencl_sdk(target: &str) {
     if target.contains("apple-darwin") { return; }
 
-    if !try_exists("./OpenCL-SDK/build").unwrap() {
+    if !Path::new("./OpenCL-SDK/CMakeLists.txt").exists() {
+        println!("cargo:warning=OpenCL-SDK submodule not found, linking with the system OpenCL library.");
+        return;
+    }
+
+    if !Path::new("./OpenCL-SDK/build").exists() {
         create_dir("./OpenCL-SDK/build").unwrap();
     }
 
@@ -15,8 +19,8 @@ fn setup_opencl_sdk(target: &str) {
             ("Unix Makefiles", "make", "false")
         };
 
-    if !try_exists("./OpenCL-SDK/build/Makefile").unwrap() {
-        let status = 
+    if !Path::new("./OpenCL-SDK/build/Makefile").exists() {
+        let status =
             Command::new("cmake")
                 .arg("..")
                 .arg("-G")
@@ -30,27 +34,34 @@ fn setup_opencl_sdk(target: &str) {
     }
 
 
-    let status = 
+    let status =
         Command::new(make_tool)
             .current_dir("./OpenCL-SDK/build")
             .status()
             .expect("Cargo build command must run in Visual Studio prompt or NMake not installed.");
 
     assert!(status.success());
-    
+
     if target.contains("pc-windows-msvc") {
         let out_dir = std::env::var("OUT_DIR").unwrap();
         std::fs::copy(
-            "./OpenCL-SDK/build/external/OpenCL-ICD-Loader/OpenCL.dll", 
+            "./OpenCL-SDK/build/external/OpenCL-ICD-Loader/OpenCL.dll",
             out_dir + "/../../../OpenCL.dll").unwrap();
     }
 }
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // CPU-only builds need neither the OpenCL SDK nor CMake.
+    if std::env::var_os("CARGO_FEATURE_OPENCL").is_none() {
+        return;
+    }
+
     let project_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
     let target = std::env::var("TARGET").unwrap();
-    
+
     setup_opencl_sdk(&target);
-    
+
     println!("cargo:rustc-link-search={}/OpenCL-SDK/build/external/OpenCL-ICD-Loader/", project_dir);
-}
\ No newline at end of file
+}
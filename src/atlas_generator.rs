@@ -1,5 +1,9 @@
-use std::{fs::File, io::Write, path::PathBuf};
-use crate::mono_image::MonoImage;
+use std::{collections::BTreeMap, fmt::Display, fs::File, io::Write, path::{Path, PathBuf}};
+use crate::backend::{SdfMode, SdfOptions};
+use crate::basic_font_generator::{FontMetrics, GlyphMetrics};
+use crate::error::{Error, Result};
+use crate::kerning::KerningPair;
+use crate::mono_image::{MonoImage, SampleFormat};
 
 struct AtlasRecord {
     character: char,
@@ -7,11 +11,17 @@ struct AtlasRecord {
     x: usize,
     y: usize,
     w: usize,
-    h: usize
+    h: usize,
+    metrics: GlyphMetrics
 }
 
+/// Packs glyph distance fields row by row into fixed size pages, saving
+/// every full page as `<page_id>.png` (or `.pfm` for float pages) in the
+/// output directory.
 pub struct AtlasGenerator {
-    page: MonoImage,
+    page: MonoImage<f32>,
+    format: SampleFormat,
+    options: SdfOptions,
 
     page_id: usize,
     output_dir: PathBuf,
@@ -23,31 +33,114 @@ pub struct AtlasGenerator {
     margin_x: usize,
     margin_y: usize,
 
-    metadata: Vec<AtlasRecord>
+    metadata: Vec<AtlasRecord>,
+    info: Vec<(String, String)>,
+    kerning: Vec<KerningPair>,
+
+    /// Index of the next character placed by [`AtlasGenerator::push_in_order`]
+    /// and the glyphs of later characters finished before it.
+    next_index: usize,
+    pending: BTreeMap<usize, Option<(char, MonoImage<f32>, GlyphMetrics)>>
 }
 
 impl AtlasGenerator {
+    /// Creates a generator writing `width` x `height` pages into
+    /// `output_dir`, keeping `margin_x`/`margin_y` pixels around every glyph.
+    /// Pages have the channels of the glyphs rendered in `mode` and are
+    /// saved in `format` with the encoding of `options`, the options the
+    /// glyphs are generated with.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         width: usize, 
         height: usize, 
         output_dir: PathBuf,
         margin_x: usize,
-        margin_y: usize) 
+        margin_y: usize,
+        mode: SdfMode,
+        format: SampleFormat,
+        options: &SdfOptions) 
         -> Self 
     {
+        let mut page = MonoImage::with_channels(width, height, mode.channels());
+        page.fill(-1.0);
+
+        let mut info = vec![
+            ("mode".to_string(), mode.to_string()),
+            ("depth".to_string(), format.to_string())
+        ];
+        info.extend(options.encoding.info(options.search_radius, options.stride as f32));
+
         AtlasGenerator {
-            page: MonoImage::new(width, height),
+            page,
+            format,
+            options: *options,
             page_id: 0,
             output_dir,
             x: 0,
             y: 0,
             current_height: 0,
             metadata: vec![],
+            info,
+            kerning: vec![],
             margin_x,
-            margin_y
+            margin_y,
+            next_index: 0,
+            pending: BTreeMap::new()
+        }
+    }
+
+    /// Adds a setting of the atlas to `info.csv`, or replaces it if `key` is
+    /// there already. The SDF mode, sample depth and distance encoding are
+    /// always written.
+    pub fn add_info(&mut self, key: &str, value: impl Display) {
+        match self.info.iter_mut().find(|(x, _)| x == key) {
+            Some((_, x)) => *x = value.to_string(),
+            None => self.info.push((key.to_string(), value.to_string()))
         }
     }
 
+    /// Adds the line metrics of the font to `info.csv` in font units, with
+    /// the em size, the output pixels per font unit as `scale` and the
+    /// search radius as `distance_range`, in output pixels. The stride and
+    /// the padding around every glyph are added as well, the padding in
+    /// output pixels and font units.
+    pub fn add_font_metrics(&mut self, metrics: &FontMetrics) {
+        let stride = self.options.stride as f32;
+        let em_size = metrics.em_size / stride;
+        let units_per_pixel = metrics.units_per_em as f32 / metrics.em_size;
+        let (padding_x, padding_y) = (metrics.padding.0 as f32, metrics.padding.1 as f32);
+
+        self.add_info("stride", self.options.stride);
+        self.add_info("padding_x", padding_x / stride);
+        self.add_info("padding_y", padding_y / stride);
+        self.add_info("padding_x_units", padding_x * units_per_pixel);
+        self.add_info("padding_y_units", padding_y * units_per_pixel);
+
+        self.add_info("units_per_em", metrics.units_per_em);
+        self.add_info("ascent", metrics.ascent);
+        self.add_info("descent", metrics.descent);
+        self.add_info("line_gap", metrics.line_gap);
+
+        if let Some((position, thickness)) = metrics.underline {
+            self.add_info("underline_position", position);
+            self.add_info("underline_thickness", thickness);
+        }
+
+        if let Some((position, thickness)) = metrics.strikeout {
+            self.add_info("strikeout_position", position);
+            self.add_info("strikeout_thickness", thickness);
+        }
+
+        self.add_info("em_size", em_size);
+        self.add_info("scale", em_size / metrics.units_per_em as f32);
+        self.add_info("distance_range", self.options.search_radius as f32 / stride);
+    }
+
+    /// Adds kerning pairs to `kerning.csv`.
+    pub fn add_kerning(&mut self, pairs: impl IntoIterator<Item = KerningPair>) {
+        self.kerning.extend(pairs);
+    }
+
     fn next_line(&mut self, character_height: usize) -> bool {
         if self.y + self.current_height + character_height < self.page.height {            
             self.y += self.current_height;
@@ -59,9 +152,12 @@ impl AtlasGenerator {
         }
     }
 
-    pub fn save_current_page(&self) {
-        self.page.save_png(
-            &self.output_dir.join(format!("{}.png", self.page_id)));
+    /// Saves the page currently being filled.
+    pub fn save_current_page(&self) -> Result<()> {
+        self.page.save_distances(
+            &self.output_dir.join(format!("{}.{}", self.page_id, self.format.extension())),
+            self.format,
+            &self.options)
     }
 
     fn next_page(&mut self) {
@@ -69,40 +165,84 @@ impl AtlasGenerator {
         self.current_height = 0;
         self.x = 0;
         self.y = 0;
-        self.page.clear_color();
+        self.page.fill(-1.0);
     }
 
-    pub fn push(&mut self, ch: char, image: &MonoImage) {
+    /// Places `image` for `ch` with its `metrics`, saving the current page
+    /// and starting a new one when it is full.
+    ///
+    /// Empty images, like the ones of spaces, only add their metrics without
+    /// taking space on the page.
+    ///
+    /// Returns [`Error::PageOverflow`] if the image is larger than a page,
+    /// and [`Error::InvalidArgument`] if it does not have the channels of
+    /// the pages.
+    pub fn push(
+        &mut self, 
+        ch: char, 
+        image: &MonoImage<f32>, 
+        metrics: &GlyphMetrics) 
+        -> Result<()> 
+    {
+        if image.width == 0 || image.height == 0 {
+            self.metadata.push(AtlasRecord {
+                character: ch,
+                page_id: self.page_id,
+                x: 0,
+                y: 0,
+                w: 0,
+                h: 0,
+                metrics: *metrics
+            });
+
+            return Ok(());
+        }
+
+        if image.channels != self.page.channels {
+            return Err(Error::InvalidArgument(
+                format!(
+                    "The image of {:?} has {} channels, the atlas pages have {}", 
+                    ch, 
+                    image.channels, 
+                    self.page.channels)));
+        }
+
         let width = image.width + 2 * self.margin_x;
         let height = image.height + 2 * self.margin_y;
 
         if width > self.page.width || height > self.page.height {
-            panic!("Page size is too small!");
+            return Err(Error::PageOverflow {
+                character: ch,
+                width,
+                height,
+                page_width: self.page.width,
+                page_height: self.page.height
+            });
         }
 
         if self.y + height >= self.page.height {
-            self.save_current_page();
+            self.save_current_page()?;
             self.next_page();
         }
 
-        if self.x + width >= self.page.width {
-            if !self.next_line(height) {
-                self.save_current_page();
-                self.next_page();
-            }
+        if self.x + width >= self.page.width && !self.next_line(height) {
+            self.save_current_page()?;
+            self.next_page();
         }
 
         self.current_height = height.max(self.current_height);
 
+        let channels = image.channels;
         for y in 0..image.height {
             for x in 0..image.width {
-                let px = image.pixels[image.offset(x, y)];
+                let source = image.offset(x, y) * channels;
                 let target = 
                     self.page.offset(
                         self.x + self.margin_x + x, 
-                        self.y + self.margin_y + y);
+                        self.y + self.margin_y + y) * channels;
 
-                self.page.pixels[target] = px;
+                self.page.pixels[target .. target + channels]
+                    .copy_from_slice(&image.pixels[source .. source + channels]);
             }
         }
 
@@ -112,30 +252,207 @@ impl AtlasGenerator {
             x: self.x + self.margin_x,
             y: self.y + self.margin_y,
             w: image.width,
-            h: image.height
+            h: image.height,
+            metrics: *metrics
         });
 
         self.x += width;
+        Ok(())
+    }
+
+    /// Places the glyph of the `index`-th character of the charset once the
+    /// glyphs of all characters before it are placed, so the layout does not
+    /// depend on the order the glyphs are finished in. `None` marks a
+    /// character without a glyph.
+    ///
+    /// Every index from 0 on must be pushed exactly once.
+    pub fn push_in_order(
+        &mut self, 
+        index: usize, 
+        glyph: Option<(char, &MonoImage<f32>, &GlyphMetrics)>) 
+        -> Result<()>
+    {
+        if index != self.next_index {
+            self.pending.insert(
+                index, 
+                glyph.map(|(ch, image, metrics)| (ch, image.clone(), *metrics)));
+            return Ok(());
+        }
+
+        if let Some((ch, image, metrics)) = glyph {
+            self.push(ch, image, metrics)?;
+        }
+
+        self.next_index += 1;
+
+        while let Some(glyph) = self.pending.remove(&self.next_index) {
+            if let Some((ch, image, metrics)) = glyph {
+                self.push(ch, &image, &metrics)?;
+            }
+
+            self.next_index += 1;
+        }
+
+        Ok(())
     }
 
-    pub fn save_metadata(&self) {
+    /// Writes `metadata.csv` with the page, rectangle and metrics of every
+    /// glyph, `info.csv` with the settings added by [`AtlasGenerator::add_info`]
+    /// and `kerning.csv` with the pairs added by [`AtlasGenerator::add_kerning`].
+    pub fn save_metadata(&self) -> Result<()> {
         let csv_path = self.output_dir.join("metadata.csv");
-        let mut out = File::create(csv_path).unwrap();
+        let mut out = File::create(csv_path)?;
 
-        out.write_all("char, page_id, x, y, width, height\n".as_bytes()).unwrap();
+        out.write_all(
+            concat!(
+                "char, page_id, x, y, width, height, ",
+                "advance, bearing_x, bearing_y, ",
+                "advance_units, bearing_x_units, bearing_y_units\n").as_bytes())?;
 
         for i in &self.metadata {
             let line = 
                 format!(
-                    "{}, {}, {}, {}, {}, {}\n", 
+                    "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}\n", 
                     i.character as usize,
                     i.page_id,
                     i.x,
                     i.y,
                     i.w,
-                    i.h);
+                    i.h,
+                    i.metrics.advance,
+                    i.metrics.bearing_x,
+                    i.metrics.bearing_y,
+                    i.metrics.advance_units,
+                    i.metrics.bearing_x_units,
+                    i.metrics.bearing_y_units);
+
+            out.write_all(line.as_bytes())?;
+        }
+
+        save_info(&self.output_dir.join("info.csv"), &self.info)?;
+
+        let mut out = File::create(self.output_dir.join("kerning.csv"))?;
 
-            out.write_all(line.as_bytes()).unwrap();
+        out.write_all("left, right, kerning, kerning_units\n".as_bytes())?;
+
+        for i in &self.kerning {
+            let line = 
+                format!(
+                    "{}, {}, {}, {}\n", 
+                    i.left as usize,
+                    i.right as usize,
+                    i.kerning,
+                    i.kerning_units);
+
+            out.write_all(line.as_bytes())?;
         }
+
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Writes `key, value` rows like the ones of `info.csv` to `out`.
+pub fn save_info(out: &Path, info: &[(String, String)]) -> Result<()> {
+    let mut out = File::create(out)?;
+
+    out.write_all("key, value\n".as_bytes())?;
+
+    for (key, value) in info {
+        out.write_all(format!("{}, {}\n", key, value).as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_push_in_order() {
+    use crate::{DistanceEncoding, SdfAlgorithm, SdfFilter};
+
+    let options = SdfOptions {
+        stride: 1,
+        search_radius: 4,
+        algorithm: SdfAlgorithm::Exact,
+        filter: SdfFilter::Point,
+        anti_aliased: false,
+        encoding: DistanceEncoding::default()
+    };
+
+    let new_atlas = || 
+        AtlasGenerator::new(64, 64, PathBuf::new(), 1, 1, SdfMode::Sdf, SampleFormat::U8, &options);
+
+    // The tenth glyph is empty like a space.
+    let glyphs: Vec<_> = 
+        (0..12)
+            .map(|i| {
+                let height = if i == 9 { 0 } else { 3 + i % 5 };
+                (char::from(b'a' + i as u8), MonoImage::new(5 + i % 4, height))
+            })
+            .collect();
+
+    // The sixth character has no glyph.
+    let mut in_order = new_atlas();
+    for (ch, image) in glyphs.iter().filter(|(ch, _)| *ch != 'f') {
+        in_order.push(*ch, image, &GlyphMetrics::default()).unwrap();
+    }
+
+    let mut shuffled = new_atlas();
+    for &i in &[3, 1, 0, 2, 7, 5, 4, 11, 6, 9, 8, 10] {
+        let (ch, image) = &glyphs[i];
+        let metrics = GlyphMetrics::default();
+        shuffled.push_in_order(i, Some((*ch, image, &metrics)).filter(|_| i != 5)).unwrap();
+    }
+
+    let rects = |atlas: &AtlasGenerator| 
+        atlas.metadata.iter().map(|i| (i.character, i.x, i.y, i.w)).collect::<Vec<_>>();
+
+    assert_eq!(rects(&shuffled), rects(&in_order));
+    assert_eq!(rects(&in_order)[8], ('j', 0, 0, 0));
+    assert_eq!(rects(&in_order)[9].1, rects(&in_order)[7].1 + 2 + rects(&in_order)[7].3);
+
+    let rgb = MonoImage::with_channels(4, 4, 3);
+    let result = in_order.push('m', &rgb, &GlyphMetrics::default());
+    assert!(matches!(result, Err(Error::InvalidArgument(_))));
+}
+
+#[test]
+fn test_add_font_metrics() {
+    use crate::{DistanceEncoding, SdfAlgorithm, SdfFilter};
+
+    let options = SdfOptions {
+        stride: 4,
+        search_radius: 8,
+        algorithm: SdfAlgorithm::Exact,
+        filter: SdfFilter::Box,
+        anti_aliased: false,
+        encoding: DistanceEncoding::default()
+    };
+
+    let metrics = FontMetrics {
+        units_per_em: 1000,
+        ascent: 800.0,
+        descent: -200.0,
+        line_gap: 0.0,
+        underline: None,
+        strikeout: None,
+        em_size: 128.0,
+        padding: (24, 8)
+    };
+
+    let mut atlas = 
+        AtlasGenerator::new(64, 64, PathBuf::new(), 1, 1, SdfMode::Sdf, SampleFormat::U8, &options);
+
+    atlas.add_info("stride", 1);
+    atlas.add_font_metrics(&metrics);
+    atlas.add_font_metrics(&metrics);
+
+    let rows = |key: &str| 
+        atlas.info.iter().filter(|(x, _)| x == key).map(|(_, x)| x.as_str()).collect::<Vec<_>>();
+
+    assert_eq!(rows("stride"), ["4"]);
+    assert_eq!(rows("padding_x"), ["6"]);
+    assert_eq!(rows("padding_y"), ["2"]);
+    assert_eq!(rows("padding_x_units"), ["187.5"]);
+    assert_eq!(rows("padding_y_units"), ["62.5"]);
+    assert_eq!(rows("em_size"), ["32"]);
+    assert_eq!(rows("distance_range"), ["2"]);
+}
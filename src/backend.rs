@@ -0,0 +1,262 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::encoding::DistanceEncoding;
+use crate::error::{Error, Result};
+use crate::mono_image::MonoImage;
+use crate::program_simd;
+
+/// How the distance to the nearest edge pixel is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdfAlgorithm {
+    /// Scans square rings out to `search_radius` around every output pixel
+    /// and takes the first edge pixel found.
+    RingSearch,
+
+    /// Exact Euclidean distance transform of the edge image, which takes
+    /// linear time independent of `search_radius`.
+    Exact,
+
+    /// Jump flooding, taking `log2(search_radius)` passes over the edge
+    /// image. It approximates the exact transform and suits OpenCL devices,
+    /// the CPU runs the same passes.
+    JumpFlood
+}
+
+impl FromStr for SdfAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "search" => Ok(SdfAlgorithm::RingSearch),
+            "exact" => Ok(SdfAlgorithm::Exact),
+            "jfa" => Ok(SdfAlgorithm::JumpFlood),
+            _ => Err(Error::InvalidArgument(format!("Unknown SDF algorithm {:?}", s)))
+        }
+    }
+}
+
+/// How output pixels are computed from the source image when downsampling
+/// by [`SdfOptions::stride`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdfFilter {
+    /// Takes the distance at the centre of every `stride` x `stride` block,
+    /// which misses features thinner than the stride.
+    Point,
+
+    /// Averages the distances of all source pixels of the block.
+    Box,
+
+    /// Takes the distance of the source pixel of the block nearest to an
+    /// edge, which keeps thin features.
+    MinAbs
+}
+
+impl FromStr for SdfFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "point" => Ok(SdfFilter::Point),
+            "box" => Ok(SdfFilter::Box),
+            "min-abs" => Ok(SdfFilter::MinAbs),
+            _ => Err(Error::InvalidArgument(format!("Unknown downsampling filter {:?}", s)))
+        }
+    }
+}
+
+/// The kind of distance field rendered for font glyphs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SdfMode {
+    /// Single-channel SDF of the rasterized glyph, generated by an
+    /// [`SdfBackend`].
+    Sdf,
+
+    /// Single-channel SDF computed analytically on the CPU from the glyph
+    /// outline at the output resolution, without rasterizing the glyph.
+    Outline,
+
+    /// Multi-channel SDF generated on the CPU from the glyph outline, which
+    /// keeps sharp corners when the median of the RGB channels is rendered.
+    Msdf,
+
+    /// MSDF in the RGB channels plus the true single-channel SDF in alpha,
+    /// for effects like glows and shadows.
+    Mtsdf
+}
+
+impl SdfMode {
+    /// Channels of the images generated in this mode.
+    pub fn channels(self) -> usize {
+        match self {
+            SdfMode::Sdf | SdfMode::Outline => 1,
+            SdfMode::Msdf => 3,
+            SdfMode::Mtsdf => 4
+        }
+    }
+}
+
+impl fmt::Display for SdfMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdfMode::Sdf => write!(f, "sdf"),
+            SdfMode::Outline => write!(f, "outline"),
+            SdfMode::Msdf => write!(f, "msdf"),
+            SdfMode::Mtsdf => write!(f, "mtsdf")
+        }
+    }
+}
+
+impl FromStr for SdfMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sdf" => Ok(SdfMode::Sdf),
+            "outline" => Ok(SdfMode::Outline),
+            "msdf" => Ok(SdfMode::Msdf),
+            "mtsdf" => Ok(SdfMode::Mtsdf),
+            _ => Err(Error::InvalidArgument(format!("Unknown SDF mode {:?}", s)))
+        }
+    }
+}
+
+/// Parameters of the SDF generation shared by every backend.
+#[derive(Clone, Copy, Debug)]
+pub struct SdfOptions {
+    /// The downsample stride, 1 will not downsample.
+    pub stride: usize,
+
+    /// The radius for edge searching in pixels of the source image.
+    pub search_radius: usize,
+
+    /// The algorithm finding the nearest edge.
+    pub algorithm: SdfAlgorithm,
+
+    /// How the distances are downsampled by `stride`. Filters other than
+    /// [`SdfFilter::Point`] generate the distances of every source pixel
+    /// first.
+    pub filter: SdfFilter,
+
+    /// Treat the source image as anti-aliased coverage and measure the
+    /// distance to the sub-pixel edge position, see
+    /// [`MonoImage::anti_aliased_generate_sdf`]. Only used by
+    /// [`SdfBackend::generate_sdf`], which then ignores `algorithm`.
+    pub anti_aliased: bool,
+
+    /// How distances are encoded when the SDF is saved.
+    pub encoding: DistanceEncoding
+}
+
+/// A device which runs the SDF pipeline.
+///
+/// Backends keep their intermediate buffers between calls, so a single
+/// backend should be reused for many images of similar size.
+pub trait SdfBackend {
+    /// A human readable name of the device.
+    fn name(&self) -> String;
+
+    /// Converts `channels` interleaved 8-bit channels to grayscale by taking
+    /// the first channel. `dst` keeps its size and must match `src`.
+    fn grayscale(&mut self, src: &[u8], channels: usize, dst: &mut MonoImage) -> Result<()>;
+
+    /// Marks the edge of the shape in `src`, see [`MonoImage::edge_detect`].
+    /// `edge` is resized to the size of `src`.
+    fn edge_detect(&mut self, src: &MonoImage, edge: &mut MonoImage) -> Result<()>;
+
+    /// Generates the SDF of an edge image created by
+    /// [`SdfBackend::edge_detect`]. `sdf` is resized to the downsampled size
+    /// and holds distances normalized to `options.search_radius`.
+    fn sdf_generate(
+        &mut self,
+        edge: &MonoImage,
+        sdf: &mut MonoImage<f32>,
+        options: &SdfOptions)
+        -> Result<()>;
+
+    /// Runs edge detection and SDF generation on `src`, keeping the edge
+    /// image on the device. `sdf` is resized to the downsampled size.
+    fn generate_sdf(
+        &mut self,
+        src: &MonoImage,
+        sdf: &mut MonoImage<f32>,
+        options: &SdfOptions)
+        -> Result<()>;
+}
+
+/// Runs the kernels of [`program_simd`] on the host, splitting the rows of
+/// the images between threads.
+pub struct CpuBackend {
+    edge: MonoImage,
+    threads: usize
+}
+
+impl CpuBackend {
+    /// Creates a backend with empty buffers running on the current thread.
+    pub fn new() -> Self {
+        Self::with_threads(1)
+    }
+
+    /// Creates a backend with empty buffers running on `threads` threads.
+    pub fn with_threads(threads: usize) -> Self {
+        Self {
+            edge: MonoImage::new(0, 0),
+            threads: threads.max(1)
+        }
+    }
+}
+
+impl Default for CpuBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SdfBackend for CpuBackend {
+    fn name(&self) -> String {
+        "CPU".to_string()
+    }
+
+    fn grayscale(&mut self, src: &[u8], channels: usize, dst: &mut MonoImage) -> Result<()> {
+        program_simd::rgba_to_grayscale(src, &mut dst.pixels, channels);
+        Ok(())
+    }
+
+    fn edge_detect(&mut self, src: &MonoImage, edge: &mut MonoImage) -> Result<()> {
+        edge.resize(src.width, src.height);
+        src.edge_detect(edge, self.threads);
+        Ok(())
+    }
+
+    fn sdf_generate(
+        &mut self,
+        edge: &MonoImage,
+        sdf: &mut MonoImage<f32>,
+        options: &SdfOptions)
+        -> Result<()>
+    {
+        sdf.resize(edge.width / options.stride, edge.height / options.stride);
+        edge.edge_generate_sdf(sdf, options, self.threads);
+        Ok(())
+    }
+
+    fn generate_sdf(
+        &mut self,
+        src: &MonoImage,
+        sdf: &mut MonoImage<f32>,
+        options: &SdfOptions)
+        -> Result<()>
+    {
+        sdf.resize(src.width / options.stride, src.height / options.stride);
+
+        if options.anti_aliased {
+            src.anti_aliased_generate_sdf(sdf, options, self.threads);
+            return Ok(());
+        }
+
+        self.edge.resize(src.width, src.height);
+        src.edge_detect(&mut self.edge, self.threads);
+        self.edge.edge_generate_sdf(sdf, options, self.threads);
+        Ok(())
+    }
+}
@@ -1,82 +1,519 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
 use clap::ArgMatches;
 use rusttype::*;
 
+use crate::backend::{SdfFilter, SdfMode, SdfOptions};
+use crate::error::{Error, Result};
+use crate::kerning::{Kerning, KerningPair};
 use crate::mono_image::MonoImage;
+use crate::outline::{self, Outline};
+use crate::program_cpu;
+
+/// Corners sharper than this many radians get differently colored edges in
+/// the MSDF.
+const MSDF_CORNER_ANGLE: f64 = 3.0;
+
+/// Placement of a glyph's distance field relative to the pen position on
+/// the baseline, in pixels of the distance field and in font units.
+///
+/// `bearing_x` is the offset of the left edge of the distance field from
+/// the pen position and `bearing_y` the height of its top edge above the
+/// baseline, both including the padding.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GlyphMetrics {
+    pub advance: f32,
+    pub bearing_x: f32,
+    pub bearing_y: f32,
+
+    pub advance_units: f32,
+    pub bearing_x_units: f32,
+    pub bearing_y_units: f32
+}
+
+/// Line metrics of a whole font in font units, positions are relative to
+/// the baseline with positive values above it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub units_per_em: u16,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+
+    /// Position and thickness of the underline, if the font has them.
+    pub underline: Option<(f32, f32)>,
 
+    /// Position and thickness of the strikeout, if the font has them.
+    pub strikeout: Option<(f32, f32)>,
+
+    /// The size of an em in pixels of the rasterized glyphs.
+    pub em_size: f32,
+
+    /// The empty space around every glyph, in pixels of the rasterized
+    /// glyphs.
+    pub padding: (usize, usize)
+}
+
+/// Rasterizes single glyphs of a font at `origin_scale` before they are
+/// converted to signed distance fields.
 pub struct BasicFontGenerator {
     font: Font<'static>,
+    font_data: Vec<u8>,
 
     origin_scale: Scale,
     v_metrics: VMetrics,
 
-    padding: (usize, usize)
+    padding: (usize, usize),
+
+    /// Keep the anti-aliased coverage of the glyphs instead of thresholding
+    /// it, for [`crate::SdfOptions::anti_aliased`].
+    pub anti_aliased: bool,
+
+    /// The kind of distance field to render, see
+    /// [`BasicFontGenerator::generate_from_outline`].
+    pub mode: SdfMode
 }
 
 impl BasicFontGenerator {
-    pub fn generate(&self, c: &str, buffer: &mut MonoImage) -> bool {
-        assert!(c.chars().nth(0).is_some() && c.chars().nth(1).is_none());
+    /// Creates a generator from the bytes of a TTF/OTF file.
+    ///
+    /// `origin_scale` is the font height in pixels used for rasterization and
+    /// `padding` is the empty space added around every glyph, in pixels of
+    /// the rasterized glyph.
+    pub fn new(font_bytes: Vec<u8>, origin_scale: f32, padding: (usize, usize)) -> Result<Self> {
+        let font = 
+            Font::try_from_vec(font_bytes.clone())
+                .ok_or_else(|| Error::FontParse("font data".to_string()))?;
+
+        if origin_scale.is_nan() || origin_scale <= 0.0 {
+            return Err(Error::InvalidArgument(
+                format!("Origin scale must be positive, got {}", origin_scale)));
+        }
+
+        let origin_scale = Scale::uniform(origin_scale);
+        let v_metrics = font.v_metrics(origin_scale);
+
+        Ok(Self {
+            font,
+            font_data: font_bytes,
+            origin_scale,
+            v_metrics,
+            padding,
+            anti_aliased: false,
+            mode: SdfMode::Sdf
+        })
+    }
+
+    /// The size of an em in pixels of the rasterized glyphs.
+    pub fn em_size(&self) -> f32 {
+        self.font.units_per_em() as f32 * self.font.scale_for_pixel_height(self.origin_scale.y)
+    }
+
+    /// Replaces the origin scale with the one rasterizing an em at
+    /// `em_size` pixels, which is not limited to whole pixels.
+    pub fn set_em_size(&mut self, em_size: f32) -> Result<()> {
+        if em_size.is_nan() || em_size <= 0.0 {
+            return Err(Error::InvalidArgument(
+                format!("Em size must be positive, got {}", em_size)));
+        }
+
+        let v_metrics = self.font.v_metrics_unscaled();
+        let height = em_size * (v_metrics.ascent - v_metrics.descent) / self.font.units_per_em() as f32;
+
+        self.origin_scale = Scale::uniform(height);
+        self.v_metrics = self.font.v_metrics(self.origin_scale);
+        Ok(())
+    }
+
+    /// Line metrics of the font. The underline and strikeout come from the
+    /// `post` and `OS/2` tables, which rusttype does not read.
+    pub fn font_metrics(&self) -> FontMetrics {
+        let v_metrics = self.font.v_metrics_unscaled();
+        let face = ttf_parser::Face::from_slice(&self.font_data, 0).ok();
+        let line = |metrics: Option<ttf_parser::LineMetrics>| 
+            metrics.map(|x| (x.position as f32, x.thickness as f32));
+
+        FontMetrics {
+            units_per_em: self.font.units_per_em(),
+            ascent: v_metrics.ascent,
+            descent: v_metrics.descent,
+            line_gap: v_metrics.line_gap,
+            underline: line(face.as_ref().and_then(|x| x.underline_metrics())),
+            strikeout: line(face.as_ref().and_then(|x| x.strikeout_metrics())),
+            em_size: self.em_size(),
+            padding: self.padding
+        }
+    }
+
+    /// Kerning of every pair of characters of `charset` the font has glyphs
+    /// for, in a distance field downsampled by `stride` and in font units.
+    /// Pairs without kerning are left out.
+    pub fn kerning(&self, charset: impl IntoIterator<Item = char>, stride: usize) -> Vec<KerningPair> {
+        let face = match ttf_parser::Face::from_slice(&self.font_data, 0) {
+            Ok(x) => x,
+            Err(_) => return Vec::new()
+        };
+
+        let kerning = Kerning::new(&face);
+        let glyphs: Vec<_> = 
+            charset
+                .into_iter()
+                .filter_map(|c| face.glyph_index(c).map(|id| (c, id)))
+                .collect();
+
+        // Positions in the charset of the characters of every glyph.
+        let mut positions: HashMap<ttf_parser::GlyphId, Vec<usize>> = HashMap::new();
+        for (i, &(_, id)) in glyphs.iter().enumerate() {
+            positions.entry(id).or_default().push(i);
+        }
+
+        let pixels_per_unit = self.em_size() / self.font.units_per_em() as f32 / stride as f32;
+        let mut pairs = Vec::new();
+
+        for &(left, left_id) in glyphs.iter().filter(|(_, id)| kerning.has_left(*id)) {
+            // Only look up the pairs the font has, if it lists them.
+            let rights: Vec<usize> =
+                match kerning.rights(left_id) {
+                    Some(ids) => {
+                        let mut rights: Vec<usize> =
+                            ids
+                                .iter()
+                                .filter_map(|id| positions.get(id))
+                                .flatten()
+                                .copied()
+                                .collect();
+
+                        rights.sort_unstable();
+                        rights
+                    },
+                    None => (0..glyphs.len()).collect()
+                };
+
+            for (right, right_id) in rights.into_iter().map(|i| glyphs[i]) {
+                let units = kerning.pair(left_id, right_id);
+                if units != 0 {
+                    pairs.push(KerningPair {
+                        left,
+                        right,
+                        kerning: units as f32 * pixels_per_unit,
+                        kerning_units: units as f32
+                    });
+                }
+            }
+        }
+
+        pairs
+    }
+
+    /// Metrics of `c` in a distance field downsampled by `stride`. Glyphs
+    /// the font has no outline for, like spaces, only have an advance.
+    ///
+    /// Returns `None` if the font has neither a glyph nor an outline for `c`.
+    pub fn metrics(&self, c: char, stride: usize) -> Option<GlyphMetrics> {
         let (padding_x, padding_y) = self.padding;
-        
+
+        // Empty glyphs have no distance field to place.
+        let (bearing_x, bearing_y) = 
+            match self.layout(c) {
+                Some((_, bounding_box)) => 
+                    (bounding_box.min.x as f32 - padding_x as f32, 
+                     self.v_metrics.ascent - bounding_box.min.y as f32 + padding_y as f32),
+                None if self.font.glyph(c).id().0 != 0 => (0.0, 0.0),
+                None => return None
+            };
+
+        // Scaling to the height of the font in font units keeps the advance
+        // an exact integer.
+        let unscaled = self.font.v_metrics_unscaled();
+        let advance_units =
+            self.font
+                .glyph(c)
+                .scaled(Scale::uniform(unscaled.ascent - unscaled.descent))
+                .h_metrics()
+                .advance_width;
+
+        let units_per_pixel = self.font.units_per_em() as f32 / self.em_size();
+        let stride = stride as f32;
+
+        Some(GlyphMetrics {
+            advance: advance_units / units_per_pixel / stride,
+            bearing_x: bearing_x / stride,
+            bearing_y: bearing_y / stride,
+            advance_units,
+            bearing_x_units: bearing_x * units_per_pixel,
+            bearing_y_units: bearing_y * units_per_pixel
+        })
+    }
+
+    /// Lays out `c` with its top at the ascent of the font and returns the
+    /// glyph with its pixel bounding box, or `None` if it is empty.
+    fn layout(&self, c: char) -> Option<(PositionedGlyph<'_>, Rect<i32>)> {
         let glyph = 
             self.font.layout(
-                c, 
+                c.encode_utf8(&mut [0; 4]), 
                 self.origin_scale, 
                 point(0.0, self.v_metrics.ascent))
-            .nth(0)
+            .next()
             .unwrap();
 
-        let bounding_box = glyph.pixel_bounding_box();
+        let bounding_box = glyph.pixel_bounding_box()?;
+        Some((glyph, bounding_box))
+    }
 
-        if bounding_box.is_none() { return false; }
+    /// Size of the rasterized glyph with the padding.
+    fn glyph_size(&self, bounding_box: &Rect<i32>) -> (usize, usize) {
+        let (padding_x, padding_y) = self.padding;
 
-        let bounding_box = bounding_box.unwrap();
+        (bounding_box.width() as usize + padding_x * 2, 
+         bounding_box.height() as usize + padding_y * 2)
+    }
 
-        let glyph_height = 
-            (self.v_metrics.ascent - self.v_metrics.descent).ceil() as usize;
+    /// Renders `c` into `buffer`, resizing it to fit the glyph.
+    ///
+    /// Returns `false` if the font has nothing to draw for `c`.
+    pub fn generate(&self, c: char, buffer: &mut MonoImage) -> bool {
+        let (padding_x, padding_y) = self.padding;
+
+        let (glyph, bounding_box) = 
+            match self.layout(c) {
+                Some(x) => x,
+                None => return false
+            };
 
-        let glyph_width =
-            bounding_box.max.x - bounding_box.min.x;
+        let (width, height) = self.glyph_size(&bounding_box);
 
         buffer.clear_color();
-        buffer.resize(
-            glyph_width as usize + padding_x * 2, 
-            glyph_height as usize + padding_y * 2);
+        buffer.resize(width, height);
 
         glyph.draw(|x, y, v|{
+            let v = 
+                if self.anti_aliased { 
+                    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+                } else if v >= 0.5 { 
+                    255 
+                } else { 
+                    0 
+                };
+
             buffer.set_pixel(
-                (x as i32 + padding_x as i32) as usize, 
-                (y as i32 + padding_y as i32 + bounding_box.min.y) as usize, 
-                if v >= 0.5 { 255 } else { 0 });
+                x as usize + padding_x, 
+                y as usize + padding_y, 
+                v);
         });
 
         true
     }
-}
 
-impl From<&ArgMatches<'_>> for BasicFontGenerator {
-    fn from(args: &ArgMatches) -> Self {
-        let font_bytes =
-            std::fs::read(args.value_of("INPUT").unwrap()).unwrap();
+    /// Renders the distance field of `c` in the outline based modes
+    /// straight from the glyph outline into `buffer`, at the size
+    /// [`crate::SdfBackend`] would downsample the output of
+    /// [`BasicFontGenerator::generate`] to.
+    ///
+    /// `buffer` becomes grayscale in [`SdfMode::Outline`], RGB in
+    /// [`SdfMode::Msdf`] and RGBA with the true distance in alpha in
+    /// [`SdfMode::Mtsdf`]. Only [`SdfMode::Outline`] uses `options.filter`,
+    /// the multi-channel modes always sample the centre of every block.
+    ///
+    /// Returns `false` if the font has nothing to draw for `c`.
+    pub fn generate_from_outline(
+        &self, 
+        c: char, 
+        buffer: &mut MonoImage<f32>, 
+        options: &SdfOptions) 
+        -> bool 
+    {
+        let (padding_x, padding_y) = self.padding;
 
-        let font = 
-            Font::try_from_vec(font_bytes)
-                .expect("Can not open font file.");
+        let (glyph, bounding_box) = 
+            match self.layout(c) {
+                Some(x) => x,
+                None => return false
+            };
 
-        let origin_scale = 
-            Scale::uniform(
-                args.value_of("origin-scale").unwrap().parse().unwrap());
+        let (width, height) = self.glyph_size(&bounding_box);
 
-        let v_metrics = font.v_metrics(origin_scale);
+        let mut outline = 
+            Outline::new(outline::Point::new(padding_x as f64, padding_y as f64));
+
+        glyph.build_outline(&mut outline);
+
+        buffer.channels = self.mode.channels();
+        buffer.resize(width / options.stride, height / options.stride);
+
+        if self.mode == SdfMode::Outline && options.filter != SdfFilter::Point {
+            let mut full = MonoImage::new(width, height);
+            outline.generate_sdf(&mut full, 1, options.search_radius);
+
+            program_cpu::sdf_downsample(
+                &full.pixels,
+                &mut buffer.pixels,
+                width,
+                height,
+                buffer.width,
+                buffer.height,
+                options.stride,
+                options.filter == SdfFilter::MinAbs,
+                1);
+        } else if self.mode == SdfMode::Outline {
+            outline.generate_sdf(buffer, options.stride, options.search_radius);
+        } else {
+            outline.color_edges(MSDF_CORNER_ANGLE);
+            outline.generate_msdf(buffer, options.stride, options.search_radius);
+        }
+
+        true
+    }
+}
+
+impl TryFrom<&ArgMatches<'_>> for BasicFontGenerator {
+    type Error = Error;
+
+    fn try_from(args: &ArgMatches) -> Result<Self> {
+        let path = args.value_of("INPUT").unwrap();
+        let font_bytes = std::fs::read(path)?;
 
         let padding =
-                (args.value_of("padding-x").unwrap().parse().unwrap(),
-                 args.value_of("padding-y").unwrap().parse().unwrap());
+                (crate::parse_arg(args, "padding-x")?,
+                 crate::parse_arg(args, "padding-y")?);
         
-        Self {
-            font,
-            origin_scale,
-            v_metrics,
-            padding
+        let mut generator = 
+            Self::new(
+                font_bytes,
+                crate::parse_arg(args, "origin-scale")?,
+                padding)
+                .map_err(|e| match e {
+                    Error::FontParse(_) => Error::FontParse(path.to_string()),
+                    e => e
+                })?;
+
+        // `--em-size` is in output pixels, glyphs are rasterized `stride`
+        // times larger.
+        if args.is_present("em-size") {
+            let stride = crate::parse_stride(args)?;
+            let em_size: f32 = crate::parse_arg(args, "em-size")?;
+            generator.set_em_size(em_size * stride as f32)?;
         }
+
+        generator.anti_aliased = args.is_present("anti-aliased");
+        generator.mode = crate::parse_arg(args, "mode")?;
+        Ok(generator)
     }
-}
\ No newline at end of file
+}
+
+/// Builds a font with no glyph outlines from its `head`, `hhea` and `maxp`
+/// tables and `tables`, for tests.
+#[cfg(test)]
+pub(crate) fn test_font(
+    units_per_em: u16, 
+    ascent: i16, 
+    descent: i16, 
+    num_glyphs: u16, 
+    tables: &[(&[u8; 4], Vec<u8>)]) 
+    -> Vec<u8> 
+{
+    let mut head = vec![0; 54];
+    head[0 .. 4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+    head[12 .. 16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+    head[18 .. 20].copy_from_slice(&units_per_em.to_be_bytes());
+
+    let mut hhea = vec![0; 36];
+    hhea[0 .. 4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+    hhea[4 .. 6].copy_from_slice(&ascent.to_be_bytes());
+    hhea[6 .. 8].copy_from_slice(&descent.to_be_bytes());
+
+    let mut maxp = 0x0000_5000u32.to_be_bytes().to_vec();
+    maxp.extend_from_slice(&num_glyphs.to_be_bytes());
+
+    let mut all: Vec<(&[u8; 4], Vec<u8>)> = 
+        vec![(b"head", head), (b"hhea", hhea), (b"maxp", maxp)];
+    all.extend(tables.iter().cloned());
+    all.sort_by_key(|(tag, _)| **tag);
+
+    let mut font = 0x0001_0000u32.to_be_bytes().to_vec();
+    font.extend_from_slice(&(all.len() as u16).to_be_bytes());
+    font.extend_from_slice(&[0; 6]);
+
+    let mut offset = 12 + 16 * all.len();
+    for (tag, data) in &all {
+        font.extend_from_slice(*tag);
+        font.extend_from_slice(&[0; 4]);
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += (data.len() + 3) & !3;
+    }
+
+    for (_, data) in &all {
+        font.extend_from_slice(data);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+
+    font
+}
+
+#[test]
+fn test_em_size() {
+    use clap::{App, Arg};
+    use crate::atlas_generator::AtlasGenerator;
+    use crate::mono_image::SampleFormat;
+    use crate::{DistanceEncoding, SdfAlgorithm, SdfFilter, SdfOptions};
+
+    let path = std::env::temp_dir().join("sdftool-em-size.ttf");
+    std::fs::write(&path, test_font(1000, 900, -300, 1, &[])).unwrap();
+
+    let app = 
+        App::new("test")
+            .arg(Arg::with_name("INPUT").index(1))
+            .arg(Arg::with_name("stride").long("stride").default_value("1"))
+            .arg(Arg::with_name("em-size").long("em-size").takes_value(true))
+            .arg(Arg::with_name("origin-scale").long("origin-scale").default_value("384"))
+            .arg(Arg::with_name("padding-x").long("padding-x").default_value("6"))
+            .arg(Arg::with_name("padding-y").long("padding-y").default_value("3"))
+            .arg(Arg::with_name("mode").long("mode").default_value("sdf"));
+
+    let generator = |args: &[&str]| {
+        let mut all = vec!["test", path.to_str().unwrap()];
+        all.extend_from_slice(args);
+        BasicFontGenerator::try_from(&app.clone().get_matches_from(all))
+    };
+
+    // Without --em-size the origin scale is the height from the ascent to
+    // the descent.
+    let origin = generator(&[]).unwrap();
+    assert!((origin.em_size() - 320.0).abs() < 1e-3);
+
+    // --em-size is in output pixels and rasterized `stride` times larger,
+    // not limited to whole pixels.
+    let generator = generator(&["--stride", "3", "--em-size", "32.5"]).unwrap();
+    assert!((generator.em_size() - 97.5).abs() < 1e-3);
+
+    let options = SdfOptions {
+        stride: 3,
+        search_radius: 6,
+        algorithm: SdfAlgorithm::Exact,
+        filter: SdfFilter::Box,
+        anti_aliased: false,
+        encoding: DistanceEncoding::default()
+    };
+
+    let dir = std::env::temp_dir().join("sdftool-em-size");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut atlas = 
+        AtlasGenerator::new(64, 64, dir.clone(), 1, 1, SdfMode::Sdf, SampleFormat::U8, &options);
+    atlas.add_font_metrics(&generator.font_metrics());
+    atlas.save_metadata().unwrap();
+
+    let info = std::fs::read_to_string(dir.join("info.csv")).unwrap();
+    let info = |key: &str| -> f32 {
+        let row = info.lines().find(|x| x.starts_with(&format!("{},", key))).unwrap();
+        row[key.len() + 1 ..].trim().parse().unwrap()
+    };
+
+    assert!((info("em_size") - 32.5).abs() < 1e-3);
+    assert!((info("scale") - 0.0325).abs() < 1e-6);
+    assert!((info("padding_x") - 2.0).abs() < 1e-6);
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
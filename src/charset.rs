@@ -1,5 +1,8 @@
-use std::{collections::BTreeSet, path::PathBuf, str::FromStr};
+use std::{collections::BTreeSet, path::PathBuf};
 
+use crate::error::Result;
+
+/// Selects the built-in charsets and additional charset files to generate.
 pub struct CharsetRequest {
     pub ascii: bool,
     pub schinese_punc: bool,
@@ -9,6 +12,12 @@ pub struct CharsetRequest {
     pub additional: Vec<PathBuf>
 }
 
+impl Default for CharsetRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CharsetRequest {
     pub fn from_args(arg: &clap::ArgMatches) -> Self {
         let mut x = Self::new();
@@ -38,14 +47,14 @@ impl CharsetRequest {
                 arg
                     .values_of("charset")
                     .unwrap()
-                    .map(PathBuf::from_str)
-                    .map(Result::unwrap)
+                    .map(PathBuf::from)
                     .collect()
         }
 
         x
     }
 
+    /// Requests the ASCII charset only.
     pub fn new() -> Self {
         Self {
             ascii: true,
@@ -57,7 +66,8 @@ impl CharsetRequest {
         }
     }
 
-    pub fn get_charset(&self) -> BTreeSet<char> {
+    /// Collects the requested characters, without line breaks.
+    pub fn get_charset(&self) -> Result<BTreeSet<char>> {
         let mut s = String::new();
 
         if self.ascii {
@@ -81,10 +91,10 @@ impl CharsetRequest {
         }
 
         for i in &self.additional {
-            s.push_str(&std::fs::read_to_string(i).unwrap());
+            s.push_str(&std::fs::read_to_string(i)?);
         }
 
-        s.chars().filter(|x| *x != '\n' && *x != '\r').collect()
+        Ok(s.chars().filter(|x| *x != '\n' && *x != '\r').collect())
     }
 }
 
@@ -95,7 +105,7 @@ fn test_charset() {
     req.schinese2 = true;
     req.schinese3 = true;
 
-    for i in req.get_charset() {
+    for i in req.get_charset().unwrap() {
         print!("{}", i);
     }
 }
@@ -1,65 +1,126 @@
 use opencl3::*;
 
+use crate::backend::{SdfAlgorithm, SdfBackend, SdfFilter, SdfOptions};
+use crate::error::{Error, Result};
+use crate::mono_image::MonoImage;
+use crate::program_cpu;
+
+/// Pixels converted at once by [`SdfBackend::grayscale`].
+const GRAYSCALE_CHUNK_PIXELS: usize = 1 << 24;
+
+/// Device buffers reused by the [`SdfBackend`] implementation.
+struct DeviceBuffers {
+    len: usize,
+    source: memory::Buffer<u8>,
+    edge: memory::Buffer<u8>,
+    result: memory::Buffer<u8>,
+
+    /// Signed squared distances written by the SDF kernels, normalized on
+    /// the host by [`program_cpu::decode_distances`].
+    distances: memory::Buffer<i32>,
+
+    /// Block sums of fixed point distances written by the `sdf_downsample`
+    /// kernel for filters other than [`SdfFilter::Point`], normalized on
+    /// the host by [`program_cpu::decode_downsampled`]. Created on first
+    /// use.
+    filtered: Option<memory::Buffer<i64>>,
+
+    /// Ping-pong buffers of `int2` nearest edge pixels for jump flooding,
+    /// created on first use.
+    seeds: Option<[memory::Buffer<i32>; 2]>
+}
+
+/// The kernels of `program.cl` compiled for a single OpenCL device.
+///
+/// Implements [`SdfBackend`] by uploading the images to the device and
+/// reading the results back.
 pub struct Context {
     pub opencl_context: context::Context,
     edge_detect: kernel::Kernel,
     sdf_generate: kernel::Kernel,
     rgba_to_grayscale: kernel::Kernel,
+    jfa_seed: kernel::Kernel,
+    jfa_step: kernel::Kernel,
+    jfa_sdf_generate: kernel::Kernel,
+    sdf_downsample: kernel::Kernel,
     command_queue: command_queue::CommandQueue,
-    pub device_name: String
+    buffers: Option<DeviceBuffers>,
+    pub device_name: String,
+
+    /// Threads running the steps without a kernel on the host, 1 by default.
+    pub host_threads: usize
 }
 
 impl Context {
-    pub fn new(device_id: *mut core::ffi::c_void) -> Self {
+    /// Compiles the kernels and creates a command queue for `device_id`.
+    pub fn new(device_id: *mut core::ffi::c_void) -> Result<Self> {
         let devices = [device::Device::new(device_id)];
 
         let opencl_context =
             context::Context::from_devices(
                 &[device_id],
-                &vec![], 
+                &[], 
                 None, 
-                std::ptr::null_mut())
-                .unwrap();
+                std::ptr::null_mut())?;
 
         let mut program = 
             program::Program::create_from_source(
                 &opencl_context,
-                include_str!("program.cl"))
-                .unwrap();
+                include_str!("program.cl"))?;
 
 
-        if let Result::Err(_) = program.build(&[device_id], "") {
-            println!("= Program build error =");
-            let build_log = program.get_build_log(device_id).unwrap();
-            println!("{}", build_log);
+        if let Err(e) = program.build(&[device_id], "") {
+            let build_log = program.get_build_log(device_id)?;
+            return Err(
+                Error::OpenCL(
+                    format!("{} when building program: {}", e, build_log.trim())));
         }
 
         let edge_detect = 
-            kernel::Kernel::create(&program, "edge_detect").unwrap();
+            kernel::Kernel::create(&program, "edge_detect")?;
 
         let sdf_generate =
-            kernel::Kernel::create(&program, "sdf_generate").unwrap();
+            kernel::Kernel::create(&program, "sdf_generate")?;
 
         let rgba_to_grayscale =
-            kernel::Kernel::create(&program, "rgba_to_grayscale").unwrap();
+            kernel::Kernel::create(&program, "rgba_to_grayscale")?;
+
+        let jfa_seed =
+            kernel::Kernel::create(&program, "jfa_seed")?;
+
+        let jfa_step =
+            kernel::Kernel::create(&program, "jfa_step")?;
+
+        let jfa_sdf_generate =
+            kernel::Kernel::create(&program, "jfa_sdf_generate")?;
+
+        let sdf_downsample =
+            kernel::Kernel::create(&program, "sdf_downsample")?;
 
         let command_queue = 
             command_queue::CommandQueue::create(
                 &opencl_context, 
                 device_id, 
                 command_queue::CL_QUEUE_OUT_OF_ORDER_EXEC_MODE_ENABLE
-            ).expect("Can not create command queue.");
+            )?;
 
-        Self {
+        Ok(Self {
             opencl_context,
             edge_detect,
             command_queue,
             sdf_generate,
             rgba_to_grayscale,
-            device_name: devices[0].name().unwrap()
-        }
+            jfa_seed,
+            jfa_step,
+            jfa_sdf_generate,
+            sdf_downsample,
+            buffers: None,
+            device_name: devices[0].name()?,
+            host_threads: 1
+        })
     }
 
+    /// Enqueues the `edge_detect` kernel, see [`crate::MonoImage::edge_detect`].
     pub fn edge_detect(
         &self, 
         src: &memory::Buffer<u8>,
@@ -67,7 +128,7 @@ impl Context {
         width: usize,
         height: usize,
         wait: &[event::Event])
-        -> event::Event
+        -> Result<event::Event>
     {
         let mut exe = kernel::ExecuteKernel::new(&self.edge_detect);
         exe
@@ -82,15 +143,17 @@ impl Context {
             exe.set_wait_event(i);
         }
 
-        exe
-            .enqueue_nd_range(&self.command_queue)
-            .unwrap()
+        Ok(exe.enqueue_nd_range(&self.command_queue)?)
     }
 
+    /// Enqueues the `sdf_generate` kernel, see
+    /// [`crate::MonoImage::edge_generate_sdf`]. Writes signed squared
+    /// distances, see [`program_cpu::decode_distances`].
+    #[allow(clippy::too_many_arguments)]
     pub fn sdf_generate(
         &self,
         edge: &memory::Buffer<u8>,
-        sdf: &mut memory::Buffer<u8>,
+        sdf: &mut memory::Buffer<i32>,
         edge_width: usize,
         edge_height: usize,
         sdf_width: usize,
@@ -98,7 +161,7 @@ impl Context {
         stride: usize,
         search_radius: usize,
         wait: &[event::Event])
-        -> event::Event
+        -> Result<event::Event>
     {
         let mut exe = kernel::ExecuteKernel::new(&self.sdf_generate);
 
@@ -117,15 +180,16 @@ impl Context {
             exe.set_wait_event(i);
         }
 
-        exe.enqueue_nd_range(&self.command_queue).unwrap()
+        Ok(exe.enqueue_nd_range(&self.command_queue)?)
     }
 
+    /// Enqueues a non-blocking upload of `src` into `dst`.
     pub fn write_buffer_to_cl<T>(
         &self,
         src: &[T],
         dst: &mut memory::Buffer<T>,
         wait: &[event::Event])
-        -> event::Event 
+        -> Result<event::Event>
     {
         let wait: Vec<*mut core::ffi::c_void> =
             wait
@@ -133,16 +197,17 @@ impl Context {
                 .map(|x| x.get())
                 .collect();
 
-        self.command_queue.enqueue_write_buffer(
-            dst, types::CL_NON_BLOCKING, 0, src, &wait).unwrap()
+        Ok(self.command_queue.enqueue_write_buffer(
+            dst, types::CL_NON_BLOCKING, 0, src, &wait)?)
     }
 
+    /// Enqueues a non-blocking download of `src` into `dst`.
     pub fn read_buffer_to_cpu<T>(
         &self,
         src: &memory::Buffer<T>,
         dst: &mut [T],
         wait: &[event::Event])
-        -> event::Event
+        -> Result<event::Event>
     {
         let wait: Vec<*mut core::ffi::c_void> =
             wait
@@ -150,75 +215,435 @@ impl Context {
                 .map(|x| x.get())
                 .collect();
 
-        self.command_queue.enqueue_read_buffer(
+        Ok(self.command_queue.enqueue_read_buffer(
             src, types::CL_NON_BLOCKING, 0, dst, &wait
-        ).unwrap()
+        )?)
     }
 
-    pub fn load_png(
+    /// Enqueues the `rgba_to_grayscale` kernel, taking the first channel of
+    /// every `stride` bytes of `src`.
+    pub fn rgba_to_grayscale(
         &self,
-        png: &str)
-        -> (memory::Buffer<u8>, usize, usize)
+        src: &memory::Buffer<u8>,
+        dst: &mut memory::Buffer<u8>,
+        size: usize,
+        stride: usize,
+        wait: &[event::Event])
+        -> Result<event::Event>
     {
-        use crate::MonoImage;
-        let (frame_info, mut buf) = MonoImage::load_png_pixels(png);
-        let image_bytes = &mut buf[..frame_info.buffer_size()];
-
-        let mut input_image = 
-            memory::Buffer::<u8>::create(
-                &self.opencl_context,
-                memory::CL_MEM_WRITE_ONLY,
-                image_bytes.len(),
-                std::ptr::null_mut()
-            ).unwrap();
-
-        self.write_buffer_to_cl(&image_bytes, &mut input_image, &[]).wait().unwrap();
-
-        let convert_image = |stride| {
-            let size = (frame_info.width * frame_info.height) as usize;
-            let mut gray_scale = 
-                memory::Buffer::<u8>::create(
-                    &self.opencl_context,
-                    0,
-                    size,
-                    std::ptr::null_mut()
-                ).unwrap();
-
-            let mut exe = kernel::ExecuteKernel::new(&self.rgba_to_grayscale);
+        let mut exe = kernel::ExecuteKernel::new(&self.rgba_to_grayscale);
             
+        exe
+            .set_arg(src)
+            .set_arg(dst)
+            .set_arg(&(stride as i32))
+            .set_global_work_sizes(&[size]);
+
+        for i in wait {
+            exe.set_wait_event(i);
+        }
+
+        Ok(exe.enqueue_nd_range(&self.command_queue)?)
+    }
+
+    /// Enqueues the jump flooding kernels, finding the nearest edge pixel of
+    /// every pixel of `edge` in the passes of
+    /// [`program_cpu::jump_flood_steps`] over the ping-pong buffers `seeds`,
+    /// and then writing the signed squared distances from them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn jump_flood_sdf_generate(
+        &self,
+        edge: &memory::Buffer<u8>,
+        seeds: &mut [memory::Buffer<i32>; 2],
+        sdf: &mut memory::Buffer<i32>,
+        edge_width: usize,
+        edge_height: usize,
+        sdf_width: usize,
+        sdf_height: usize,
+        stride: usize,
+        search_radius: usize,
+        wait: &[event::Event])
+        -> Result<event::Event>
+    {
+        let mut exe = kernel::ExecuteKernel::new(&self.jfa_seed);
+        exe
+            .set_arg(edge)
+            .set_arg(&seeds[0])
+            .set_arg(&(edge_width as i32))
+            .set_arg(&(edge_height as i32))
+            .set_global_work_sizes(&[edge_width, edge_height]);
+
+        for i in wait {
+            exe.set_wait_event(i);
+        }
+
+        let mut last_event = exe.enqueue_nd_range(&self.command_queue)?;
+
+        let mut current = 0;
+        for step in program_cpu::jump_flood_steps(search_radius) {
+            let (a, b) = seeds.split_at_mut(1);
+            let (src, dst) = 
+                if current == 0 { (&a[0], &mut b[0]) } else { (&b[0], &mut a[0]) };
+
+            let mut exe = kernel::ExecuteKernel::new(&self.jfa_step);
             exe
-                .set_arg(&input_image)
-                .set_arg(&mut gray_scale)
-                .set_arg(&stride)
-                .set_global_work_sizes(&[size])
-                .enqueue_nd_range(&self.command_queue)
-                .unwrap()
-                .wait()
-                .unwrap();
-
-            gray_scale
-        };
-
-        let image_buffer =
-            match frame_info.color_type {
-                | png::ColorType::Grayscale 
-                | png::ColorType::GrayscaleAlpha => 
-                    input_image,
-                | png::ColorType::Rgb => convert_image(3),
-                | png::ColorType::Rgba => convert_image(4),
-                | _ => panic!("PNG frame must in grayscale/rgb type.")
-            };
-
-        (
-            image_buffer, 
-            frame_info.width as usize, 
-            frame_info.height as usize
-        )
+                .set_arg(src)
+                .set_arg(dst)
+                .set_arg(&(edge_width as i32))
+                .set_arg(&(edge_height as i32))
+                .set_arg(&(step as i32))
+                .set_global_work_sizes(&[edge_width, edge_height])
+                .set_wait_event(&last_event);
+
+            last_event = exe.enqueue_nd_range(&self.command_queue)?;
+            current = 1 - current;
+        }
+
+        let mut exe = kernel::ExecuteKernel::new(&self.jfa_sdf_generate);
+        exe
+            .set_arg(edge)
+            .set_arg(&seeds[current])
+            .set_arg(sdf)
+            .set_arg(&(edge_width as i32))
+            .set_arg(&(edge_height as i32))
+            .set_arg(&(sdf_width as i32))
+            .set_arg(&(sdf_height as i32))
+            .set_arg(&(stride as i32))
+            .set_arg(&(search_radius as i32))
+            .set_global_work_sizes(&[sdf_width, sdf_height])
+            .set_wait_event(&last_event);
+
+        Ok(exe.enqueue_nd_range(&self.command_queue)?)
+    }
+
+    /// Enqueues the `sdf_downsample` kernel, see
+    /// [`program_cpu::sdf_downsample_distances`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn sdf_downsample(
+        &self,
+        src: &memory::Buffer<i32>,
+        dst: &mut memory::Buffer<i64>,
+        src_width: usize,
+        src_height: usize,
+        dst_width: usize,
+        dst_height: usize,
+        stride: usize,
+        search_radius: usize,
+        min_abs: bool,
+        wait: &[event::Event])
+        -> Result<event::Event>
+    {
+        let mut exe = kernel::ExecuteKernel::new(&self.sdf_downsample);
+
+        exe
+            .set_arg(src)
+            .set_arg(dst)
+            .set_arg(&(src_width as i32))
+            .set_arg(&(src_height as i32))
+            .set_arg(&(dst_width as i32))
+            .set_arg(&(dst_height as i32))
+            .set_arg(&(stride as i32))
+            .set_arg(&(search_radius as i32))
+            .set_arg(&(min_abs as i32))
+            .set_global_work_sizes(&[dst_width, dst_height]);
+
+        for i in wait {
+            exe.set_wait_event(i);
+        }
+
+        Ok(exe.enqueue_nd_range(&self.command_queue)?)
+    }
+
+    /// Runs the kernels of `options.algorithm` on `buffers.edge` after
+    /// `wait`, downsampled with `options.filter`, and normalizes the
+    /// distances into `sdf` on the host.
+    fn generate_distances(
+        &self,
+        buffers: &mut DeviceBuffers,
+        edge_width: usize,
+        edge_height: usize,
+        sdf: &mut MonoImage<f32>,
+        options: &SdfOptions,
+        wait: &[event::Event])
+        -> Result<()>
+    {
+        if options.filter == SdfFilter::Point || options.stride == 1 {
+            let mut distances = vec![0; sdf.pixels.len()];
+            let wait_sdf_generate = 
+                self.enqueue_distances(
+                    buffers, 
+                    edge_width, 
+                    edge_height, 
+                    sdf.width, 
+                    sdf.height, 
+                    options, 
+                    wait)?;
+
+            self.read_buffer_to_cpu(&buffers.distances, &mut distances, &[wait_sdf_generate])?.wait()?;
+            program_cpu::decode_distances(&distances, &mut sdf.pixels, options.search_radius);
+            return Ok(());
+        }
+
+        let full_options = SdfOptions { stride: 1, ..*options };
+        let wait_full = 
+            self.enqueue_distances(
+                buffers, 
+                edge_width, 
+                edge_height, 
+                edge_width, 
+                edge_height, 
+                &full_options, 
+                wait)?;
+
+        if buffers.filtered.is_none() {
+            buffers.filtered = Some(self.create_buffer(memory::CL_MEM_READ_WRITE, buffers.len)?);
+        }
+
+        let min_abs = options.filter == SdfFilter::MinAbs;
+        let filtered = buffers.filtered.as_mut().unwrap();
+        let wait_downsample = 
+            self.sdf_downsample(
+                &buffers.distances,
+                filtered,
+                edge_width,
+                edge_height,
+                sdf.width,
+                sdf.height,
+                options.stride,
+                options.search_radius,
+                min_abs,
+                &[wait_full])?;
+
+        let mut reduced = vec![0; sdf.pixels.len()];
+        self.read_buffer_to_cpu(filtered, &mut reduced, &[wait_downsample])?.wait()?;
+
+        let samples = if min_abs { 1 } else { options.stride * options.stride };
+        program_cpu::decode_downsampled(&reduced, &mut sdf.pixels, samples, options.search_radius);
+        Ok(())
+    }
+
+    /// Enqueues the kernels of `options.algorithm` writing the distances
+    /// sampled at the centre of every block into `buffers.distances`.
+    #[allow(clippy::too_many_arguments)]
+    fn enqueue_distances(
+        &self,
+        buffers: &mut DeviceBuffers,
+        edge_width: usize,
+        edge_height: usize,
+        sdf_width: usize,
+        sdf_height: usize,
+        options: &SdfOptions,
+        wait: &[event::Event])
+        -> Result<event::Event>
+    {
+        match options.algorithm {
+            SdfAlgorithm::JumpFlood => {
+                if buffers.seeds.is_none() {
+                    buffers.seeds = Some([
+                        self.create_buffer(memory::CL_MEM_READ_WRITE, buffers.len * 2)?,
+                        self.create_buffer(memory::CL_MEM_READ_WRITE, buffers.len * 2)?
+                    ]);
+                }
+
+                self.jump_flood_sdf_generate(
+                    &buffers.edge,
+                    buffers.seeds.as_mut().unwrap(),
+                    &mut buffers.distances,
+                    edge_width,
+                    edge_height,
+                    sdf_width,
+                    sdf_height,
+                    options.stride,
+                    options.search_radius,
+                    wait)
+            },
+            _ => 
+                self.sdf_generate(
+                    &buffers.edge,
+                    &mut buffers.distances,
+                    edge_width,
+                    edge_height,
+                    sdf_width,
+                    sdf_height,
+                    options.stride,
+                    options.search_radius,
+                    wait)
+        }
+    }
+
+    fn create_buffer<T>(&self, flags: types::cl_mem_flags, len: usize) -> Result<memory::Buffer<T>> {
+        Ok(memory::Buffer::<T>::create(
+            &self.opencl_context,
+            flags,
+            len.max(1),
+            std::ptr::null_mut()
+        )?)
+    }
+
+    /// Takes the reused device buffers, growing them to hold at least `len`
+    /// pixels. They must be put back into `self.buffers` after use.
+    fn take_buffers(&mut self, len: usize) -> Result<DeviceBuffers> {
+        match self.buffers.take() {
+            Some(buffers) if buffers.len >= len => Ok(buffers),
+            _ => Ok(DeviceBuffers {
+                len,
+                source: self.create_buffer(memory::CL_MEM_READ_WRITE, len)?,
+                edge: self.create_buffer(memory::CL_MEM_READ_WRITE, len)?,
+                result: self.create_buffer(memory::CL_MEM_READ_WRITE, len)?,
+                distances: self.create_buffer(memory::CL_MEM_READ_WRITE, len)?,
+                filtered: None,
+                seeds: None
+            })
+        }
+    }
+}
+
+impl SdfBackend for Context {
+    fn name(&self) -> String {
+        self.device_name.clone()
+    }
+
+    fn grayscale(&mut self, src: &[u8], channels: usize, dst: &mut MonoImage) -> Result<()> {
+        // Large images are converted in chunks, so the buffers fit on the
+        // device like the tiles of `crate::generate_sdf_tiled`.
+        let chunk = GRAYSCALE_CHUNK_PIXELS.min(dst.pixels.len());
+        let mut buffers = self.take_buffers(chunk * channels)?;
+
+        let context: &Context = self;
+        let result = (|| {
+            for (src, dst) in src.chunks(chunk * channels).zip(dst.pixels.chunks_mut(chunk)) {
+                let wait_load = 
+                    context.write_buffer_to_cl(src, &mut buffers.source, &[])?;
+                
+                let wait_convert =
+                    context.rgba_to_grayscale(
+                        &buffers.source, 
+                        &mut buffers.result, 
+                        dst.len(), 
+                        channels, 
+                        &[wait_load])?;
+
+                context.read_buffer_to_cpu(&buffers.result, dst, &[wait_convert])?.wait()?;
+            }
+
+            Ok(())
+        })();
+
+        self.buffers = Some(buffers);
+        result
+    }
+
+    fn edge_detect(&mut self, src: &MonoImage, edge: &mut MonoImage) -> Result<()> {
+        edge.resize(src.width, src.height);
+        let mut buffers = self.take_buffers(src.pixels.len())?;
+
+        let context: &Context = self;
+        let result = (|| {
+            let wait_load = 
+                context.write_buffer_to_cl(&src.pixels, &mut buffers.source, &[])?;
+
+            let wait_edge_detect =
+                context.edge_detect(
+                    &buffers.source, 
+                    &mut buffers.edge, 
+                    src.width, 
+                    src.height, 
+                    &[wait_load])?;
+
+            context.read_buffer_to_cpu(&buffers.edge, &mut edge.pixels, &[wait_edge_detect])?.wait()?;
+            Ok(())
+        })();
+
+        self.buffers = Some(buffers);
+        result
+    }
+
+    fn sdf_generate(
+        &mut self,
+        edge: &MonoImage,
+        sdf: &mut MonoImage<f32>,
+        options: &SdfOptions)
+        -> Result<()>
+    {
+        sdf.resize(edge.width / options.stride, edge.height / options.stride);
+
+        if options.algorithm == SdfAlgorithm::Exact {
+            // There is no kernel for the exact transform, it runs on the host.
+            edge.edge_generate_sdf(sdf, options, self.host_threads);
+            return Ok(());
+        }
+
+        let mut buffers = self.take_buffers(edge.pixels.len())?;
+
+        let context: &Context = self;
+        let result = (|| {
+            let wait_load = 
+                context.write_buffer_to_cl(&edge.pixels, &mut buffers.edge, &[])?;
+
+            context.generate_distances(
+                &mut buffers,
+                edge.width,
+                edge.height,
+                sdf,
+                options,
+                &[wait_load])
+        })();
+
+        self.buffers = Some(buffers);
+        result
+    }
+
+    fn generate_sdf(
+        &mut self,
+        src: &MonoImage,
+        sdf: &mut MonoImage<f32>,
+        options: &SdfOptions)
+        -> Result<()>
+    {
+        if options.anti_aliased {
+            sdf.resize(src.width / options.stride, src.height / options.stride);
+            src.anti_aliased_generate_sdf(sdf, options, self.host_threads);
+            return Ok(());
+        }
+
+        if options.algorithm == SdfAlgorithm::Exact {
+            let mut edge = MonoImage::new(0, 0);
+            self.edge_detect(src, &mut edge)?;
+            return self.sdf_generate(&edge, sdf, options);
+        }
+
+        sdf.resize(src.width / options.stride, src.height / options.stride);
+        let mut buffers = self.take_buffers(src.pixels.len())?;
+
+        let context: &Context = self;
+        let result = (|| {
+            let wait_load = 
+                context.write_buffer_to_cl(&src.pixels, &mut buffers.source, &[])?;
+
+            let wait_edge_detect =
+                context.edge_detect(
+                    &buffers.source, 
+                    &mut buffers.edge, 
+                    src.width, 
+                    src.height, 
+                    &[wait_load])?;
+
+            context.generate_distances(
+                &mut buffers,
+                src.width,
+                src.height,
+                sdf,
+                options,
+                &[wait_edge_detect])
+        })();
+
+        self.buffers = Some(buffers);
+        result
     }
 }
 
 impl Drop for Context {
     fn drop(&mut self) {
-        self.command_queue.finish().unwrap();
+        let _ = self.command_queue.finish();
     }
 }
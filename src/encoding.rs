@@ -0,0 +1,196 @@
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::mono_image::Sample;
+
+/// How signed distances are mapped to the samples of saved images.
+///
+/// A distance inside the shape is divided by `inside_spread`, one outside
+/// by `outside_spread`, and the result `t` is clamped to `0.0..=1.0` and
+/// raised to the power `curve`. The encoded level then runs from
+/// `zero_level` at the edge up to 1.0 at `inside_spread` inside the shape
+/// and down to 0.0 at `outside_spread` outside of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DistanceEncoding {
+    /// Distance inside the shape mapped to level 1.0, in pixels of the
+    /// source image like [`crate::SdfOptions::search_radius`]. `None` uses
+    /// the search radius.
+    pub inside_spread: Option<f32>,
+
+    /// Distance outside the shape mapped to level 0.0, see
+    /// [`DistanceEncoding::inside_spread`].
+    pub outside_spread: Option<f32>,
+
+    /// The level of the edge, from 0.0 to 1.0.
+    pub zero_level: f32,
+
+    /// Exponent applied to the distances, 1.0 is linear and smaller values
+    /// keep more precision near the edge.
+    pub curve: f32
+}
+
+impl Default for DistanceEncoding {
+    /// Linear encoding over the search radius around the level 0.5.
+    fn default() -> Self {
+        Self {
+            inside_spread: None,
+            outside_spread: None,
+            zero_level: 0.5,
+            curve: 1.0
+        }
+    }
+}
+
+impl DistanceEncoding {
+    /// Encodes a distance normalized to `search_radius` pixels, like the
+    /// distances generated by [`crate::SdfBackend::generate_sdf`].
+    pub fn encode<T: Sample>(&self, distance: f32, search_radius: usize) -> T {
+        let (spread, sign) =
+            if distance < 0.0 {
+                (self.outside_spread, -1.0)
+            } else {
+                (self.inside_spread, 1.0)
+            };
+
+        let t =
+            match spread {
+                Some(spread) => distance.abs() * (search_radius as f32 / spread),
+                None => distance.abs()
+            };
+
+        let t = t.min(1.0);
+        let t = if self.curve == 1.0 { t } else { t.powf(self.curve) };
+
+        T::from_level(self.zero_level, sign * t)
+    }
+
+    /// The inside and outside spread in pixels of the output image, which
+    /// is `scale` times smaller than the source.
+    pub fn output_spreads(&self, search_radius: usize, scale: f32) -> (f32, f32) {
+        let output = |spread: Option<f32>| spread.unwrap_or(search_radius as f32) / scale;
+        (output(self.inside_spread), output(self.outside_spread))
+    }
+
+    /// The `inside_spread`, `outside_spread` (in pixels of the output image,
+    /// see [`DistanceEncoding::output_spreads`]), `zero_level` and `curve`
+    /// rows of `info.csv`.
+    pub fn info(&self, search_radius: usize, scale: f32) -> Vec<(String, String)> {
+        let (inside_spread, outside_spread) = self.output_spreads(search_radius, scale);
+
+        vec![
+            ("inside_spread".to_string(), inside_spread.to_string()),
+            ("outside_spread".to_string(), outside_spread.to_string()),
+            ("zero_level".to_string(), self.zero_level.to_string()),
+            ("curve".to_string(), self.curve.to_string())
+        ]
+    }
+
+    /// The smallest search radius from `search_radius` up that measures the
+    /// distances out to both spreads.
+    pub fn search_radius(&self, search_radius: usize) -> usize {
+        self.inside_spread
+            .iter()
+            .chain(self.outside_spread.iter())
+            .map(|spread| spread.ceil() as usize)
+            .fold(search_radius, usize::max)
+    }
+
+    /// Returns [`Error::InvalidArgument`] if a parameter is out of range,
+    /// or if a spread is larger than `search_radius`, which would never
+    /// reach the end levels.
+    pub fn validate(&self, search_radius: usize) -> Result<()> {
+        for spread in self.inside_spread.iter().chain(self.outside_spread.iter()) {
+            if spread.is_nan() || *spread <= 0.0 {
+                return Err(Error::InvalidArgument(
+                    format!("Spread must be positive, got {}", spread)));
+            }
+
+            if *spread > search_radius as f32 {
+                return Err(Error::InvalidArgument(
+                    format!(
+                        "Spread of {} source pixels is larger than the search radius {}", 
+                        spread, 
+                        search_radius)));
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.zero_level) {
+            return Err(Error::InvalidArgument(
+                format!("Zero level must be between 0 and 1, got {}", self.zero_level)));
+        }
+
+        if self.curve.is_nan() || self.curve <= 0.0 {
+            return Err(Error::InvalidArgument(
+                format!("Curve must be positive, got {}", self.curve)));
+        }
+
+        Ok(())
+    }
+}
+
+/// A spread given on the command line, in pixels of the output image
+/// (`4` or `4px`) or in em units of a font (`0.1em`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spread {
+    Pixels(f32),
+    Em(f32)
+}
+
+impl Spread {
+    /// Converts the spread to pixels of the source image, which is `scale`
+    /// times larger than the output. `em_size` is the size of an em in
+    /// pixels of the source image, if any.
+    pub fn to_source_pixels(self, scale: f32, em_size: Option<f32>) -> Result<f32> {
+        match (self, em_size) {
+            (Spread::Pixels(x), _) => Ok(x * scale),
+            (Spread::Em(x), Some(em_size)) => Ok(x * em_size),
+            (Spread::Em(_), None) =>
+                Err(Error::InvalidArgument("Spreads in em units need a font".to_string()))
+        }
+    }
+}
+
+impl FromStr for Spread {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgument(format!("Invalid spread {:?}", s));
+
+        if let Some(em) = s.strip_suffix("em") {
+            em.trim().parse().map(Spread::Em).map_err(|_| invalid())
+        } else {
+            s.trim_end_matches("px").trim().parse().map(Spread::Pixels).map_err(|_| invalid())
+        }
+    }
+}
+
+#[test]
+fn test_encode() {
+    let encoding = DistanceEncoding::default();
+
+    for i in -100..=100 {
+        let distance = i as f32 / 100.0;
+        let sign = if distance < 0.0 { -1 } else { 1 };
+        let expected = ((distance.abs() * 127.0) as i32 * sign + 127) as u8;
+
+        assert_eq!(encoding.encode::<u8>(distance, 24), expected, "at {}", distance);
+    }
+
+    let encoding = DistanceEncoding {
+        inside_spread: Some(6.0),
+        outside_spread: Some(12.0),
+        zero_level: 0.75,
+        curve: 0.5
+    };
+
+    assert_eq!(encoding.encode::<f32>(0.0, 24), 0.75);
+    assert_eq!(encoding.encode::<f32>(0.0625, 24), 0.875);
+    assert_eq!(encoding.encode::<f32>(-0.125, 24), 0.375);
+    assert_eq!(encoding.encode::<f32>(-1.0, 24), 0.0);
+    assert_eq!(encoding.encode::<u8>(-1.0, 24), 0);
+    assert_eq!(encoding.encode::<u8>(1.0, 24), 254);
+
+    assert_eq!(encoding.search_radius(8), 12);
+    assert!(encoding.validate(8).is_err());
+    assert!(encoding.validate(encoding.search_radius(8)).is_ok());
+}
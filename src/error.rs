@@ -0,0 +1,125 @@
+use std::fmt;
+
+/// Errors returned by every fallible operation of this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+
+    /// The input file is not a valid PNG image.
+    PngDecode(png::DecodingError),
+
+    /// Encoding the output PNG image failed.
+    PngEncode(png::EncodingError),
+
+    /// The PNG image is valid, but its color type or bit depth is not
+    /// supported.
+    UnsupportedColorType(png::ColorType, png::BitDepth),
+
+    /// The font file can not be parsed.
+    FontParse(String),
+
+    /// An OpenCL call failed.
+    OpenCL(String),
+
+    /// A command line or API argument has an invalid value.
+    InvalidArgument(String),
+
+    /// A glyph does not fit into an empty atlas page.
+    PageOverflow {
+        character: char,
+        width: usize,
+        height: usize,
+        page_width: usize,
+        page_height: usize
+    }
+}
+
+/// `Result` with [`Error`] as the error type.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The process exit code for this kind of error, so scripts calling the
+    /// command line tool can tell the categories apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) | Error::PngEncode(_) => 2,
+            Error::PngDecode(_) => 3,
+            Error::UnsupportedColorType(_, _) => 4,
+            Error::FontParse(_) => 5,
+            Error::OpenCL(_) => 6,
+            Error::InvalidArgument(_) => 7,
+            Error::PageOverflow { .. } => 8
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::PngDecode(e) => write!(f, "Can not decode PNG: {}", e),
+            Error::PngEncode(e) => write!(f, "Can not encode PNG: {}", e),
+            Error::UnsupportedColorType(color, depth) =>
+                write!(
+                    f,
+                    "Unsupported PNG format {:?} with {} bits, must be 8 bits grayscale/rgb",
+                    color,
+                    *depth as u8),
+            Error::FontParse(path) => write!(f, "Can not parse font {}", path),
+            Error::OpenCL(e) => write!(f, "OpenCL error: {}", e),
+            Error::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+            Error::PageOverflow { character, width, height, page_width, page_height } =>
+                write!(
+                    f,
+                    "Page size {}x{} is too small for {:?} ({}x{})",
+                    page_width,
+                    page_height,
+                    character,
+                    width,
+                    height)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::PngDecode(e) => Some(e),
+            Error::PngEncode(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(e: png::DecodingError) -> Self {
+        match e {
+            png::DecodingError::IoError(e) => Error::Io(e),
+            e => Error::PngDecode(e)
+        }
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => Error::Io(e),
+            e => Error::PngEncode(e)
+        }
+    }
+}
+
+#[cfg(feature = "opencl")]
+impl From<opencl3::error_codes::ClError> for Error {
+    fn from(e: opencl3::error_codes::ClError) -> Self {
+        Error::OpenCL(e.to_string())
+    }
+}
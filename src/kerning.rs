@@ -0,0 +1,375 @@
+use std::ops::Range;
+
+use ttf_parser::gpos::{PairAdjustment, PositioningSubtable};
+use ttf_parser::{kern, Face, GlyphId, LazyArray16, Tag};
+
+/// Kerning between two characters, in pixels of the distance field and in
+/// font units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KerningPair {
+    pub left: char,
+    pub right: char,
+    pub kerning: f32,
+    pub kerning_units: f32
+}
+
+/// Horizontal kerning of a font, from the pair adjustments of the `kern`
+/// feature in the `GPOS` table or, like shapers do, from the legacy `kern`
+/// table if there are none.
+pub struct Kerning<'a> {
+    /// Pair adjustment subtables of every `kern` lookup, in lookup order.
+    lookups: Vec<Vec<PairAdjustment<'a>>>,
+    subtables: Vec<kern::Subtable<'a>>
+}
+
+impl<'a> Kerning<'a> {
+    pub fn new(face: &Face<'a>) -> Self {
+        let tables = face.tables();
+        let mut lookups = Vec::new();
+
+        if let Some(gpos) = tables.gpos {
+            let mut indices: Vec<u16> =
+                gpos.features
+                    .into_iter()
+                    .filter(|feature| feature.tag == Tag::from_bytes(b"kern"))
+                    .flat_map(|feature| feature.lookup_indices)
+                    .collect();
+
+            indices.sort_unstable();
+            indices.dedup();
+
+            lookups =
+                indices
+                    .into_iter()
+                    .filter_map(|index| gpos.lookups.get(index))
+                    .map(|lookup|
+                        lookup.subtables
+                            .into_iter::<PositioningSubtable>()
+                            .filter_map(|subtable| match subtable {
+                                PositioningSubtable::Pair(x) => Some(x),
+                                _ => None
+                            })
+                            .collect::<Vec<_>>())
+                    .filter(|subtables| !subtables.is_empty())
+                    .collect();
+        }
+
+        let subtables =
+            match tables.kern {
+                Some(kern) if lookups.is_empty() =>
+                    kern.subtables
+                        .into_iter()
+                        .filter(|x|
+                            x.horizontal &&
+                            !x.variable &&
+                            !x.has_cross_stream &&
+                            !x.has_state_machine)
+                        .collect(),
+                _ => Vec::new()
+            };
+
+        Self { lookups, subtables }
+    }
+
+    /// Returns `false` if no pair starting with `left` can be kerned, which
+    /// saves looking up every right glyph.
+    pub fn has_left(&self, left: GlyphId) -> bool {
+        let in_kern = 
+            self.subtables
+                .iter()
+                .any(|subtable| match subtable.format {
+                    kern::Format::Format0(x) => !left_pairs(x.pairs, left).is_empty(),
+                    _ => true
+                });
+
+        in_kern ||
+            self.lookups
+                .iter()
+                .flatten()
+                .any(|subtable| subtable.coverage().contains(left))
+    }
+
+    /// The right glyphs of the pairs starting with `left` in the `kern`
+    /// table, in glyph order, or `None` if the kerning is looked up by
+    /// class and any glyph may follow `left`.
+    pub fn rights(&self, left: GlyphId) -> Option<Vec<GlyphId>> {
+        if !self.lookups.is_empty() {
+            return None;
+        }
+
+        let mut rights = Vec::new();
+        for subtable in &self.subtables {
+            match subtable.format {
+                kern::Format::Format0(x) =>
+                    rights.extend(
+                        left_pairs(x.pairs, left)
+                            .filter_map(|i| x.pairs.get(i))
+                            .map(|pair| pair.right())),
+                _ => return None
+            }
+        }
+
+        rights.sort_unstable();
+        rights.dedup();
+        Some(rights)
+    }
+
+    /// The change of the advance of `left` when `right` follows it, in font
+    /// units.
+    pub fn pair(&self, left: GlyphId, right: GlyphId) -> i32 {
+        let gpos: i32 =
+            self.lookups
+                .iter()
+                .filter_map(|subtables|
+                    subtables
+                        .iter()
+                        .find_map(|subtable| pair_adjustment(subtable, left, right)))
+                .sum();
+
+        let kern: i32 =
+            self.subtables
+                .iter()
+                .filter_map(|subtable| subtable.glyphs_kerning(left, right))
+                .map(i32::from)
+                .sum();
+
+        gpos + kern
+    }
+}
+
+/// Indices of the pairs starting with `left` in the `pairs` of a format 0
+/// `kern` subtable, which are sorted by their left and then right glyph.
+fn left_pairs(pairs: LazyArray16<kern::KerningPair>, left: GlyphId) -> Range<u16> {
+    let first = |glyph: u32| {
+        let (mut low, mut high) = (0, pairs.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match pairs.get(middle) {
+                Some(pair) if pair.pair < glyph << 16 => low = middle + 1,
+                _ => high = middle
+            }
+        }
+
+        low
+    };
+
+    first(u32::from(left.0)) .. first(u32::from(left.0) + 1)
+}
+
+/// The advance adjustment of `left` by the first subtable of a lookup
+/// covering the pair, `None` lets the next subtable try.
+fn pair_adjustment(subtable: &PairAdjustment, left: GlyphId, right: GlyphId) -> Option<i32> {
+    let records =
+        match subtable {
+            PairAdjustment::Format1 { coverage, sets } =>
+                sets.get(coverage.get(left)?)?.get(right)?,
+            PairAdjustment::Format2 { coverage, classes, matrix } => {
+                if !coverage.contains(left) {
+                    return None;
+                }
+
+                // A covered left glyph ends the search even without a
+                // record for its classes.
+                match matrix.get((classes.0.get(left), classes.1.get(right))) {
+                    Some(x) => x,
+                    None => return Some(0)
+                }
+            }
+        };
+
+    Some(i32::from(records.0.x_advance))
+}
+
+#[test]
+fn test_left_pairs() {
+    // Pairs of a format 0 subtable as big endian left, right and value.
+    let pairs: &[(u16, u16, i16)] = 
+        &[(3, 5, -10), (3, 9, 20), (7, 3, -5), (7, 4, 5), (7, 8, 1), (12, 0, 2)];
+    let data: Vec<u8> = 
+        pairs
+            .iter()
+            .flat_map(|(left, right, value)| 
+                [left.to_be_bytes(), right.to_be_bytes(), value.to_be_bytes()].concat())
+            .collect();
+
+    let pairs = LazyArray16::new(&data);
+    assert_eq!(left_pairs(pairs, GlyphId(3)), 0..2);
+    assert_eq!(left_pairs(pairs, GlyphId(7)), 2..5);
+    assert_eq!(left_pairs(pairs, GlyphId(12)), 5..6);
+    assert!(left_pairs(pairs, GlyphId(0)).is_empty());
+    assert!(left_pairs(pairs, GlyphId(5)).is_empty());
+    assert!(left_pairs(pairs, GlyphId(13)).is_empty());
+}
+
+/// Big endian 16-bit values of font tables.
+#[cfg(test)]
+fn words(values: &[i32]) -> Vec<u8> {
+    values.iter().flat_map(|x| (*x as u16).to_be_bytes()).collect()
+}
+
+/// A `kern` table with a format 0 subtable of `(left, right, value)` pairs.
+#[cfg(test)]
+fn kern_table(pairs: &[(i32, i32, i32)]) -> Vec<u8> {
+    let count = pairs.len() as i32;
+    let mut table = words(&[0, 1, 0, 14 + 6 * count, 0x0001, count, 0, 0, 0]);
+    for (left, right, value) in pairs {
+        table.extend(words(&[*left, *right, *value]));
+    }
+
+    table
+}
+
+/// A `GPOS` table with one `kern` feature using `indices` of `lookups`,
+/// each a list of pair adjustment subtables.
+#[cfg(test)]
+fn gpos_table(indices: &[i32], lookups: &[Vec<Vec<u8>>]) -> Vec<u8> {
+    let mut lookup_list = words(&[lookups.len() as i32]);
+    let mut data = Vec::new();
+    for subtables in lookups {
+        let offset = 2 + 2 * lookups.len() + data.len();
+        lookup_list.extend(words(&[offset as i32]));
+
+        let mut offset = 6 + 2 * subtables.len();
+        data.extend(words(&[2, 0, subtables.len() as i32]));
+        for subtable in subtables {
+            data.extend(words(&[offset as i32]));
+            offset += subtable.len();
+        }
+
+        data.extend(subtables.concat());
+    }
+    lookup_list.extend(data);
+
+    let mut feature_list = words(&[1]);
+    feature_list.extend(b"kern");
+    feature_list.extend(words(&[8, 0, indices.len() as i32]));
+    feature_list.extend(words(indices));
+
+    let mut table = words(&[1, 0, 10, 12, 12 + feature_list.len() as i32, 0]);
+    table.extend(feature_list);
+    table.extend(lookup_list);
+    table
+}
+
+/// A format 1 pair adjustment subtable of the x advances of `(left,
+/// [(right, value)])` pairs.
+#[cfg(test)]
+fn pair_pos_1(sets: &[(i32, &[(i32, i32)])]) -> Vec<u8> {
+    let mut offset = 10 + 2 * sets.len();
+    let mut offsets = Vec::new();
+    let mut data = Vec::new();
+    for (_, pairs) in sets {
+        offsets.push(offset as i32);
+        data.extend(words(&[pairs.len() as i32]));
+        for (right, value) in *pairs {
+            data.extend(words(&[*right, *value]));
+        }
+
+        offset += 2 + 4 * pairs.len();
+    }
+
+    let mut subtable = words(&[1, offset as i32, 0x0004, 0, sets.len() as i32]);
+    subtable.extend(words(&offsets));
+    subtable.extend(data);
+    subtable.extend(words(&[1, sets.len() as i32]));
+    subtable.extend(words(&sets.iter().map(|(left, _)| *left).collect::<Vec<_>>()));
+    subtable
+}
+
+/// A format 2 pair adjustment subtable of the x advances in `matrix` by the
+/// classes of glyphs starting at `first.0` and `second.0`.
+#[cfg(test)]
+fn pair_pos_2(
+    coverage: &[i32], 
+    first: (i32, &[i32]), 
+    second: (i32, &[i32]), 
+    matrix: &[&[i32]]) 
+    -> Vec<u8> 
+{
+    let class_def = |(start, classes): (i32, &[i32])| {
+        let mut class_def = words(&[1, start, classes.len() as i32]);
+        class_def.extend(words(classes));
+        class_def
+    };
+
+    let matrix: Vec<i32> = matrix.concat();
+    let coverage_offset = 16 + 2 * matrix.len() as i32;
+    let first_offset = coverage_offset + 4 + 2 * coverage.len() as i32;
+    let second_offset = first_offset + 6 + 2 * first.1.len() as i32;
+
+    let first_count = first.1.iter().max().map_or(1, |x| x + 1);
+    let second_count = matrix.len() as i32 / first_count;
+
+    let mut subtable = 
+        words(&[
+            2, coverage_offset, 0x0004, 0, first_offset, second_offset, first_count, second_count]);
+    subtable.extend(words(&matrix));
+    subtable.extend(words(&[1, coverage.len() as i32]));
+    subtable.extend(words(coverage));
+    subtable.extend(class_def(first));
+    subtable.extend(class_def(second));
+    subtable
+}
+
+#[test]
+fn test_gpos_pairs() {
+    use crate::basic_font_generator::test_font;
+
+    // The first lookup tries the pairs of glyphs before their classes, the
+    // second adds to both.
+    let first = 
+        vec![
+            pair_pos_1(&[(1, &[(3, -50), (4, -20)]), (2, &[(3, 15)])]),
+            pair_pos_2(&[1, 5], (5, &[1]), (3, &[1, 2, 0, 2, 1]), &[&[0, 7, 9], &[0, -30, -40]])
+        ];
+    let second = vec![pair_pos_1(&[(5, &[(3, -5)])])];
+
+    let gpos = gpos_table(&[1, 0, 1], &[first, second]);
+    let kern = kern_table(&[(1, 3, 100), (7, 8, 10)]);
+    let font = test_font(1000, 800, -200, 10, &[(b"GPOS", gpos), (b"kern", kern)]);
+    let face = Face::from_slice(&font, 0).unwrap();
+    let kerning = Kerning::new(&face);
+
+    let pair = |left, right| kerning.pair(GlyphId(left), GlyphId(right));
+
+    // The kern table is ignored when GPOS kerns.
+    assert_eq!(pair(1, 3), -50);
+    assert_eq!(pair(7, 8), 0);
+
+    assert_eq!(pair(1, 4), -20);
+    assert_eq!(pair(2, 3), 15);
+
+    // Pairs of glyph 1 missing from the first subtable use its class 0.
+    assert_eq!(pair(1, 5), 0);
+    assert_eq!(pair(1, 6), 9);
+    assert_eq!(pair(1, 7), 7);
+    assert_eq!(pair(5, 4), -40);
+    assert_eq!(pair(5, 5), 0);
+    assert_eq!(pair(5, 7), -30);
+    assert_eq!(pair(5, 3), -30 - 5);
+    assert_eq!(pair(2, 4), 0);
+
+    assert!(kerning.has_left(GlyphId(1)));
+    assert!(kerning.has_left(GlyphId(5)));
+    assert!(!kerning.has_left(GlyphId(3)));
+    assert_eq!(kerning.rights(GlyphId(1)), None);
+}
+
+#[test]
+fn test_kern_pairs() {
+    use crate::basic_font_generator::test_font;
+
+    let kern = kern_table(&[(1, 3, -40), (1, 4, 10), (6, 2, 25)]);
+    let font = test_font(1000, 800, -200, 10, &[(b"kern", kern)]);
+    let face = Face::from_slice(&font, 0).unwrap();
+    let kerning = Kerning::new(&face);
+
+    assert_eq!(kerning.pair(GlyphId(1), GlyphId(3)), -40);
+    assert_eq!(kerning.pair(GlyphId(1), GlyphId(4)), 10);
+    assert_eq!(kerning.pair(GlyphId(6), GlyphId(2)), 25);
+    assert_eq!(kerning.pair(GlyphId(2), GlyphId(6)), 0);
+
+    assert!(kerning.has_left(GlyphId(6)));
+    assert!(!kerning.has_left(GlyphId(2)));
+    assert_eq!(kerning.rights(GlyphId(1)), Some(vec![GlyphId(3), GlyphId(4)]));
+}
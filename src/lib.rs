@@ -0,0 +1,347 @@
+//! Signed distance field generation for images and fonts.
+//!
+//! The `sdftool` binary is a thin command line client on top of this crate,
+//! and everything it does is available from here:
+//!
+//! * [`MonoImage`] holds a grayscale image and runs the CPU kernels from
+//!   [`program_cpu`] (edge detection and SDF generation), vectorized in
+//!   [`program_simd`] where the CPU supports it.
+//! * [`generate_sdf`] turns a symbol image into its signed distance field,
+//!   encoded as set by [`DistanceEncoding`], and [`generate_sdf_tiled`] does
+//!   the same tile by tile for images too large for the device.
+//!   [`generate_sdf_streamed`] also reads and writes the files a band of
+//!   tiles at a time through the [`streaming`] readers and writers, for
+//!   images too large for memory.
+//! * [`BasicFontGenerator`] rasterizes glyphs from a TTF/OTF font,
+//!   [`CharsetRequest`] collects the characters to render and
+//!   [`AtlasGenerator`] packs the resulting SDF glyphs into atlas pages
+//!   with a `metadata.csv` describing every glyph, an `info.csv` with
+//!   the settings of the atlas and a `kerning.csv` with the
+//!   [`kerning`] pairs of the charset.
+//! * [`outline::Outline`] generates single and multi-channel SDFs
+//!   (MSDF/MTSDF) straight from glyph outlines.
+//! * [`generate_font_atlas`] ties the font pieces together on a single thread.
+//! * [`SdfBackend`] runs the pipeline on a device, either on the CPU with
+//!   [`CpuBackend`] or on an OpenCL device with `context::Context` when the
+//!   `opencl` feature is enabled (the default).
+//!
+//! Fallible operations return [`Result`] with [`Error`].
+//!
+//! ```no_run
+//! use sdftool::{CpuBackend, DistanceEncoding, MonoImage, SdfAlgorithm, SdfFilter, SdfOptions, generate_sdf};
+//!
+//! # fn main() -> sdftool::Result<()> {
+//! let image = MonoImage::load_from_file("symbol.png")?;
+//! let options = SdfOptions {
+//!     stride: 4,
+//!     search_radius: 128,
+//!     algorithm: SdfAlgorithm::Exact,
+//!     filter: SdfFilter::Point,
+//!     anti_aliased: false,
+//!     encoding: DistanceEncoding::default()
+//! };
+//! let sdf: MonoImage = generate_sdf(&mut CpuBackend::new(), &image, &options)?;
+//! sdf.save_png(std::path::Path::new("symbol-sdf.png"))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+use std::str::FromStr;
+
+pub mod atlas_generator;
+pub mod backend;
+pub mod basic_font_generator;
+pub mod charset;
+#[cfg(feature = "opencl")]
+pub mod context;
+pub mod encoding;
+pub mod error;
+pub mod kerning;
+pub mod mono_image;
+pub mod outline;
+pub mod program_cpu;
+pub mod program_simd;
+pub mod streaming;
+pub mod work_queue;
+
+pub use atlas_generator::{AtlasGenerator, save_info};
+pub use backend::{CpuBackend, SdfAlgorithm, SdfBackend, SdfFilter, SdfMode, SdfOptions};
+pub use basic_font_generator::{BasicFontGenerator, FontMetrics, GlyphMetrics};
+pub use charset::CharsetRequest;
+pub use encoding::DistanceEncoding;
+pub use error::{Error, Result};
+pub use kerning::KerningPair;
+pub use mono_image::{MonoImage, Sample, SampleFormat};
+pub use streaming::{DistanceWriter, PngRowReader};
+
+/// Generates the signed distance field of a grayscale `image` with
+/// `backend`.
+///
+/// Pixels `>= 128` are inside the shape. The result is `options.stride`
+/// times smaller than the input, and distances are normalized to
+/// `options.search_radius` pixels of the input image, then encoded with
+/// `options.encoding` (by default around the midpoint value 127 for `u8`).
+pub fn generate_sdf<T: Sample>(
+    backend: &mut dyn SdfBackend,
+    image: &MonoImage,
+    options: &SdfOptions)
+    -> Result<MonoImage<T>>
+{
+    let mut sdf = MonoImage::new(0, 0);
+    backend.generate_sdf(image, &mut sdf, options)?;
+    Ok(sdf.encode(options))
+}
+
+/// Same as [`SdfBackend::generate_sdf`], but runs the backend on tiles of
+/// at most `tile_size` x `tile_size` output pixels and stitches them into
+/// `sdf`, so the device only holds a tile at a time. A `tile_size` of 0
+/// processes the whole image at once.
+///
+/// Every tile is extended by a halo of at least `options.search_radius`
+/// pixels of `image`, which holds every edge its distances depend on, so
+/// the result matches generating the whole image, except for ties between
+/// equally near edges in [`SdfAlgorithm::JumpFlood`] and anti-aliased mode.
+pub fn generate_sdf_tiled(
+    backend: &mut dyn SdfBackend,
+    image: &MonoImage,
+    sdf: &mut MonoImage<f32>,
+    options: &SdfOptions,
+    tile_size: usize)
+    -> Result<()>
+{
+    let stride = options.stride;
+    let (width, height) = (image.width / stride, image.height / stride);
+
+    if tile_size == 0 || (width <= tile_size && height <= tile_size) {
+        return backend.generate_sdf(image, sdf, options);
+    }
+
+    *sdf = MonoImage::new(width, height);
+    generate_band(backend, image, 0, height, sdf, 0, options, tile_size)
+}
+
+/// Same as [`generate_sdf_tiled`], but reads the PNG file `input` and
+/// writes the distances to `output` as `format` in bands of one row of
+/// tiles, so only a band of the input and of the output is in memory.
+pub fn generate_sdf_streamed(
+    backend: &mut dyn SdfBackend,
+    input: &str,
+    output: &Path,
+    format: SampleFormat,
+    options: &SdfOptions,
+    tile_size: usize)
+    -> Result<()>
+{
+    let mut reader = PngRowReader::open(input)?;
+    let stride = options.stride;
+    let (width, height) = (reader.width / stride, reader.height / stride);
+    let mut writer = DistanceWriter::create(output, width, height, format)?;
+
+    let band_size = if tile_size == 0 { height.max(1) } else { tile_size };
+    let halo = halo(options);
+
+    // Source rows from `band_top` on, enough for the band and its halo.
+    let mut band = MonoImage::new(reader.width, 0);
+    let mut band_top = 0;
+    let mut band_sdf = MonoImage::new(0, 0);
+
+    for band_y in (0..height).step_by(band_size) {
+        let rows = band_size.min(height - band_y);
+        let top = band_y.saturating_sub(halo) * stride;
+        let bottom = 
+            if band_y + rows + halo >= height { 
+                reader.height 
+            } else { 
+                (band_y + rows + halo) * stride 
+            };
+
+        band.pixels.drain(.. (top - band_top) * band.width);
+        band.height -= top - band_top;
+        band_top = top;
+
+        let missing = bottom - band_top - band.height;
+        reader.read_rows(&mut band, missing, backend)?;
+
+        band_sdf.resize(width, rows);
+        generate_band(backend, &band, band_top, height, &mut band_sdf, band_y, options, tile_size)?;
+        writer.write_rows(&band_sdf, options)?;
+    }
+
+    writer.finish()
+}
+
+/// Halo of the tiles in output pixels. Whole blocks, so the tiles sample
+/// the same positions as the image. Edge detection and anti-aliasing look
+/// at 2 more pixels.
+fn halo(options: &SdfOptions) -> usize {
+    (options.search_radius + 2).div_ceil(options.stride)
+}
+
+/// Generates the output rows `band_y..band_y + sdf.height` of an image of
+/// `height` output rows into `sdf`, in tiles of `tile_size` (0 for the
+/// whole width). `band` holds the source rows from `band_top` on, which
+/// cover the rows of `sdf` and their halo.
+#[allow(clippy::too_many_arguments)]
+fn generate_band(
+    backend: &mut dyn SdfBackend,
+    band: &MonoImage,
+    band_top: usize,
+    height: usize,
+    sdf: &mut MonoImage<f32>,
+    band_y: usize,
+    options: &SdfOptions,
+    tile_size: usize)
+    -> Result<()>
+{
+    let stride = options.stride;
+    let width = sdf.width;
+    let halo = halo(options);
+    let tile_size = if tile_size == 0 { width.max(sdf.height).max(1) } else { tile_size };
+
+    let mut tile_sdf = MonoImage::new(0, 0);
+
+    for tile_y in (band_y..band_y + sdf.height).step_by(tile_size) {
+        for tile_x in (0..width).step_by(tile_size) {
+            let tile_width = tile_size.min(width - tile_x);
+            let tile_height = tile_size.min(band_y + sdf.height - tile_y);
+
+            let x0 = tile_x.saturating_sub(halo);
+            let y0 = tile_y.saturating_sub(halo);
+            let x1 = (tile_x + tile_width + halo).min(width);
+            let y1 = (tile_y + tile_height + halo).min(height);
+
+            // Tiles at the border keep the pixels beyond the last block.
+            let source_x1 = if x1 == width { band.width } else { x1 * stride };
+            let source_y1 = if y1 == height { band_top + band.height } else { y1 * stride };
+
+            let tile = 
+                band.crop(
+                    x0 * stride, 
+                    y0 * stride - band_top, 
+                    source_x1 - x0 * stride, 
+                    source_y1 - y0 * stride);
+
+            backend.generate_sdf(&tile, &mut tile_sdf, options)?;
+            sdf.paste(
+                &tile_sdf, 
+                tile_x - x0, 
+                tile_y - y0, 
+                tile_x, 
+                tile_y - band_y, 
+                tile_width, 
+                tile_height);
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders the distance field of `ch` into `sdf` in the mode of
+/// `generator`, using `glyph` as scratch buffer for the rasterized glyph.
+///
+/// Returns the metrics of the glyph, or `None` if the font has nothing to
+/// draw for `ch`. Glyphs without an outline, like spaces, leave `sdf`
+/// empty.
+pub fn render_glyph(
+    backend: &mut dyn SdfBackend,
+    generator: &BasicFontGenerator,
+    ch: char,
+    glyph: &mut MonoImage,
+    sdf: &mut MonoImage<f32>,
+    options: &SdfOptions)
+    -> Result<Option<GlyphMetrics>>
+{
+    let metrics = 
+        match generator.metrics(ch, options.stride) {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+    let drawn =
+        match generator.mode {
+            SdfMode::Sdf => {
+                let drawn = generator.generate(ch, glyph);
+                if drawn {
+                    backend.generate_sdf(glyph, sdf, options)?;
+                }
+
+                drawn
+            },
+            _ => generator.generate_from_outline(ch, sdf, options)
+        };
+
+    if !drawn {
+        sdf.resize(0, 0);
+    }
+
+    Ok(Some(metrics))
+}
+
+/// Renders every character of `charset` with `generator`, converts it to a
+/// distance field with `backend` and pushes it into `atlas`, after adding
+/// the font metrics with [`AtlasGenerator::add_font_metrics`].
+///
+/// Pages and metadata are not saved, call
+/// [`AtlasGenerator::save_current_page`] and
+/// [`AtlasGenerator::save_metadata`] when done. Returns the characters
+/// which can not be rendered by the font.
+pub fn generate_font_atlas(
+    backend: &mut dyn SdfBackend,
+    generator: &BasicFontGenerator,
+    charset: impl IntoIterator<Item = char>,
+    atlas: &mut AtlasGenerator,
+    options: &SdfOptions)
+    -> Result<Vec<char>>
+{
+    let mut glyph = MonoImage::new(0, 0);
+    let mut sdf = MonoImage::new(0, 0);
+    let mut missing = Vec::new();
+
+    atlas.add_font_metrics(&generator.font_metrics());
+
+    for ch in charset {
+        match render_glyph(backend, generator, ch, &mut glyph, &mut sdf, options)? {
+            Some(metrics) => atlas.push(ch, &sdf, &metrics)?,
+            None => missing.push(ch)
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Parses the value of the command line argument `name`.
+///
+/// Returns [`Error::InvalidArgument`] if it is missing or can not be parsed.
+pub fn parse_arg<T: FromStr>(args: &clap::ArgMatches, name: &str) -> Result<T> {
+    let value = 
+        args
+            .value_of(name)
+            .ok_or_else(|| Error::InvalidArgument(format!("--{} is required", name)))?;
+
+    value
+        .parse()
+        .map_err(|_| Error::InvalidArgument(format!("Invalid value {:?} for --{}", value, name)))
+}
+
+/// Parses the `--stride` command line argument.
+///
+/// Returns [`Error::InvalidArgument`] if it is missing, can not be parsed
+/// or is 0.
+pub fn parse_stride(args: &clap::ArgMatches) -> Result<usize> {
+    match parse_arg(args, "stride")? {
+        0 => Err(Error::InvalidArgument("Stride must be greater than or equal to 1.".to_string())),
+        stride => Ok(stride)
+    }
+}
+
+/// Parses the `--search-radius` command line argument.
+///
+/// Returns [`Error::InvalidArgument`] if it is missing, can not be parsed
+/// or is 0, which would leave no distance to normalize by.
+pub fn parse_search_radius(args: &clap::ArgMatches) -> Result<usize> {
+    match parse_arg(args, "search-radius")? {
+        0 => Err(Error::InvalidArgument("Search radius must be greater than or equal to 1.".to_string())),
+        search_radius => Ok(search_radius)
+    }
+}
@@ -1,33 +1,28 @@
-mod context;
-mod charset;
-mod basic_font_generator;
-mod mono_image;
-mod atlas_generator;
-mod program_cpu;
-
-use atlas_generator::AtlasGenerator;
-use basic_font_generator::*;
-use charset::CharsetRequest;
 use clap::{App, Arg, ArgMatches, SubCommand};
-use context::Context;
+#[cfg(feature = "opencl")]
 use opencl3::*;
-use opencl3::platform::Platform;
+use std::convert::TryFrom;
 use std::path::Path;
-use std::sync::{Arc, Condvar, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
 use progress_bar::progress_bar::ProgressBar;
 use progress_bar::color::{Color, Style};
+use sdftool::{AtlasGenerator, BasicFontGenerator, CharsetRequest, CpuBackend, DistanceEncoding, Error, MonoImage, Result, SampleFormat, SdfBackend, SdfOptions, parse_arg, parse_search_radius, parse_stride};
+use sdftool::encoding::Spread;
+use sdftool::work_queue::WorkQueue;
+#[cfg(feature = "opencl")]
+use sdftool::context::Context;
 
-use crate::mono_image::MonoImage;
-
+#[cfg(feature = "opencl")]
 const DEVICE_TYPE : types::cl_bitfield = 
     device::CL_DEVICE_TYPE_ALL;
 
 fn main() {
     let search_radius_arg =
         Arg::with_name("search-radius")
-            .help("Set the radius for edge searching")
+            .help("Set the radius for edge searching, raised to the largest spread")
             .long("search-radius")
             .short("r")
             .default_value("128")
@@ -41,12 +36,100 @@ fn main() {
             .default_value("4")
             .multiple(false);
 
+    let algorithm_arg =
+        Arg::with_name("algorithm")
+            .help("Set the algorithm finding the nearest edge")
+            .long("algorithm")
+            .short("a")
+            .possible_values(&["search", "exact", "jfa"])
+            .default_value("search")
+            .multiple(false);
+
+    let filter_arg =
+        Arg::with_name("filter")
+            .help("Set how distances are downsampled by the stride, box and min-abs measure every source pixel")
+            .long("filter")
+            .possible_values(&["point", "box", "min-abs"])
+            .default_value("point")
+            .multiple(false);
+
+    let anti_aliased_arg =
+        Arg::with_name("anti-aliased")
+            .help("Measure distances to the sub-pixel edge of anti-aliased images")
+            .long("anti-aliased");
+
+    let depth_arg =
+        Arg::with_name("depth")
+            .help("Set the bits per sample of the output, 8 and 16 write PNG, 32 writes float PFM")
+            .long("depth")
+            .short("d")
+            .possible_values(&["8", "16", "32"])
+            .default_value("8")
+            .multiple(false);
+
+    let inside_spread_arg =
+        Arg::with_name("inside-spread")
+            .help("Set the distance inside the shape encoded as white, in output pixels or em units like 0.1em [default: search radius]")
+            .long("inside-spread")
+            .takes_value(true)
+            .multiple(false);
+
+    let outside_spread_arg =
+        Arg::with_name("outside-spread")
+            .help("Set the distance outside the shape encoded as black, in output pixels or em units like 0.1em [default: search radius]")
+            .long("outside-spread")
+            .takes_value(true)
+            .multiple(false);
+
+    let zero_level_arg =
+        Arg::with_name("zero-level")
+            .help("Set the level of the edge, from 0 (black) to 1 (white)")
+            .long("zero-level")
+            .default_value("0.5")
+            .multiple(false);
+
+    let curve_arg =
+        Arg::with_name("curve")
+            .help("Set the exponent applied to distances, 1 is linear and smaller values keep more precision near the edge")
+            .long("curve")
+            .default_value("1")
+            .multiple(false);
+
     let cpu_arg =
         Arg::with_name("cpu")
             .help("Do not use OpenCL.")
             .long("cpu");
 
-    let mut app = 
+    let platform_id_arg =
+        Arg::with_name("platform-id")
+            .help("Only use OpenCL devices of the platform with this index")
+            .long("platform-id")
+            .takes_value(true)
+            .multiple(false);
+
+    let device_id_arg =
+        Arg::with_name("device-id")
+            .help("Only use the OpenCL devices with this index in their platform")
+            .long("device-id")
+            .takes_value(true)
+            .multiple(false);
+
+    let device_name_arg =
+        Arg::with_name("device-name")
+            .help("Only use OpenCL devices whose name contains this text, ignoring case")
+            .long("device-name")
+            .takes_value(true)
+            .multiple(false);
+
+    let device_type_arg =
+        Arg::with_name("device-type")
+            .help("Only use OpenCL devices of this type")
+            .long("device-type")
+            .possible_values(&["all", "gpu", "cpu", "accelerator"])
+            .default_value("all")
+            .multiple(false);
+
+    let app = 
         App::new("SDF Tool")
             .bin_name("sdftool")
             .version("v0.1")
@@ -60,24 +143,39 @@ fn main() {
                     .required(true)
                 )
                 .arg(Arg::with_name("OUTPUT")
-                    .help("Output path for SDF image in PNG format")
+                    .help("Output path for SDF image in PNG or PFM format")
                     .multiple(false)
                     .required(true))
-                .arg(Arg::with_name("platform-id")
-                    .help("Select the platform to use")
-                    .long("--platform-id")
-                    .multiple(false)
-                    .default_value("0"))
-                .arg(Arg::with_name("device-id")
-                    .help("Select the device to use")
-                    .long("--device-id")
-                    .multiple(false)
-                    .default_value("0"))
+                .arg(platform_id_arg.clone())
+                .arg(device_id_arg.clone())
+                .arg(device_name_arg.clone())
+                .arg(device_type_arg.clone())
                 .arg(search_radius_arg.clone())
                 .arg(stride_arg.clone())
+                .arg(Arg::with_name("tile-size")
+                    .help("Process the image in tiles of this many output pixels squared and read and write it a row of tiles at a time, 0 processes it at once")
+                    .long("tile-size")
+                    .default_value("1024")
+                    .multiple(false))
+                .arg(Arg::with_name("threads")
+                    .help("Set the number of threads running on the CPU, 0 uses all cores")
+                    .long("threads")
+                    .default_value("0")
+                    .multiple(false))
+                .arg(Arg::with_name("output-size")
+                    .help("Set the output size like 64x64, overriding --stride")
+                    .long("output-size")
+                    .takes_value(true)
+                    .multiple(false))
+                .arg(algorithm_arg.clone())
+                .arg(filter_arg.clone())
+                .arg(anti_aliased_arg.clone())
+                .arg(depth_arg.clone())
+                .arg(inside_spread_arg.clone())
+                .arg(outside_spread_arg.clone())
+                .arg(zero_level_arg.clone())
+                .arg(curve_arg.clone())
                 .arg(cpu_arg.clone()))
-            .subcommand(SubCommand::with_name("cl-devices")
-                .about("List OpenCL devices"))
             .subcommand(SubCommand::with_name("font")
                 .about("Create the sdf font")
                 .arg(Arg::with_name("INPUT")
@@ -89,8 +187,32 @@ fn main() {
                     .required(true)
                     .multiple(false))
                 .arg(cpu_arg)
+                .arg(platform_id_arg)
+                .arg(device_id_arg)
+                .arg(device_name_arg)
+                .arg(device_type_arg)
+                .arg(Arg::with_name("cpu-workers")
+                    .long("cpu-workers")
+                    .takes_value(true)
+                    .multiple(false)
+                    .help("Set the number of CPU workers [default: one per core not driven by an OpenCL worker]"))
                 .arg(search_radius_arg.default_value("24"))
                 .arg(stride_arg.default_value("8"))
+                .arg(algorithm_arg)
+                .arg(filter_arg)
+                .arg(anti_aliased_arg)
+                .arg(depth_arg)
+                .arg(inside_spread_arg)
+                .arg(outside_spread_arg)
+                .arg(zero_level_arg)
+                .arg(curve_arg)
+                .arg(Arg::with_name("mode")
+                    .long("mode")
+                    .short("m")
+                    .possible_values(&["sdf", "outline", "msdf", "mtsdf"])
+                    .default_value("sdf")
+                    .multiple(false)
+                    .help("Set the kind of distance field, all but sdf are generated from outlines on the CPU"))
                 .arg(Arg::with_name("no-ascii")
                     .long("no-ascii")
                     .multiple(false)
@@ -115,6 +237,10 @@ fn main() {
                     .long("origin-scale")
                     .default_value("384")
                     .help("Basic font scale before downsample"))
+                .arg(Arg::with_name("em-size")
+                    .long("em-size")
+                    .takes_value(true)
+                    .help("Set the size of an em in output pixels whatever the --stride, overriding --origin-scale"))
                 .arg(Arg::with_name("page-width")
                     .long("page-width")
                     .default_value("1024")
@@ -147,392 +273,261 @@ fn main() {
                     .takes_value(true)
                     .help("Additional charset to generate")));
 
-    if std::env::args().nth(1) == None {
+    #[cfg(feature = "opencl")]
+    let app = 
+        app.subcommand(SubCommand::with_name("cl-devices")
+            .about("List OpenCL devices"));
+
+    let mut app = app;
+
+    if std::env::args().nth(1).is_none() {
         app.print_help().unwrap();
     }
 
     let matches = app.get_matches();
 
-    if let Some(_) = matches.subcommand_matches("cl-devices") {
-        show_cl_devices();
-    }
-    
-    else if let Some(matches) = matches.subcommand_matches("symbol") {
-        symbol(matches);
-    }
+    let result =
+        match matches.subcommand() {
+            ("symbol", Some(matches)) => symbol(matches),
+            ("font", Some(matches)) => font(matches),
+            #[cfg(feature = "opencl")]
+            ("cl-devices", _) => show_cl_devices(),
+            _ => Ok(())
+        };
 
-    else if let Some(matches) = matches.subcommand_matches("font") {
-        font(matches);
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
     }
 }
 
-fn font(args: &ArgMatches) {
-    let basic_gen = Arc::new(BasicFontGenerator::from(args));
-    let charset = CharsetRequest::from_args(args).get_charset();
-    
-    let progress_bar = Arc::new(Mutex::new(ProgressBar::new(charset.len())));
+/// Most characters a font worker takes from the queue at once.
+const FONT_BATCH_SIZE: usize = 16;
+
+/// State shared by the font workers.
+struct FontJob {
+    basic_gen: BasicFontGenerator,
+    options: SdfOptions,
+    progress_bar: Mutex<ProgressBar>,
+    atlas_generator: Mutex<AtlasGenerator>,
+    /// Characters with their index in the charset.
+    queue: WorkQueue<(usize, char)>,
+    done: AtomicUsize
+}
 
-    {
-        progress_bar
+impl FontJob {
+    fn print_info(&self, info: &str, message: &str, color: Color) {
+        self.progress_bar
             .lock()
             .unwrap()
-            .set_action("Rendering", Color::LightCyan, Style::Bold);
+            .print_info(info, message, color, Style::Bold);
     }
 
-    let task = Arc::new(Mutex::new(None));
+    /// Advances the progress bar by `count` finished characters.
+    fn advance(&self, count: usize) {
+        let mut progress_bar = self.progress_bar.lock().unwrap();
+        let done = self.done.fetch_add(count, Ordering::Relaxed) + count;
+        progress_bar.set_progression(done);
+    }
+}
 
-    let run = Arc::new(AtomicBool::new(true));
+fn font_worker(job: &FontJob, backend: &mut dyn SdfBackend) -> Result<()> {
+    let mut basic_gen_buf = MonoImage::new(0, 0);
+    let mut sdf_buf = MonoImage::<f32>::new(0, 0);
+    let mut batch = Vec::new();
+
+    while job.queue.pop_batch(&mut batch) {
+        let count = batch.len();
+
+        for (index, ch) in batch.drain(..) {
+            let metrics =
+                sdftool::render_glyph(
+                    backend, 
+                    &job.basic_gen, 
+                    ch, 
+                    &mut basic_gen_buf, 
+                    &mut sdf_buf, 
+                    &job.options)?;
+
+            if metrics.is_none() {
+                job.print_info("Warning", &format!("Can not render {}", ch), Color::Yellow);
+            }
 
-    let cvar = Arc::new(Condvar::new());
+            job.atlas_generator
+                .lock()
+                .unwrap()
+                .push_in_order(index, metrics.as_ref().map(|metrics| (ch, &sdf_buf, metrics)))?;
+        }
 
-    let (stride, search_radius) = 
-        get_stride_and_search_radius(&args);
+        job.advance(count);
+    }
 
-    let atlas_generator = Arc::new(Mutex::new(AtlasGenerator::new(
-        args.value_of("page-width").unwrap().parse().unwrap(),
-        args.value_of("page-height").unwrap().parse().unwrap(),
-        args.value_of("OUTDIR").unwrap().parse().unwrap(),
-        args.value_of("margin-x").unwrap().parse().unwrap(),
-        args.value_of("margin-y").unwrap().parse().unwrap()
-    )));
+    Ok(())
+}
 
-    let enable_opencl_workers = !args.is_present("cpu");
-    let mut cpu_workers_num = num_cpus::get() as i32;
+fn spawn_font_worker<F>(job: Arc<FontJob>, create_backend: F) -> JoinHandle<Result<()>>
+where
+    F: FnOnce() -> Result<Box<dyn SdfBackend>> + Send + 'static
+{
+    thread::spawn(move || {
+        let result = 
+            create_backend()
+                .and_then(|mut backend| font_worker(&job, backend.as_mut()));
+
+        if result.is_err() {
+            job.queue.cancel();
+        }
 
-    let create_opencl_workers = |platform: Platform| {
-        let devices =
-            platform
-                .get_devices(DEVICE_TYPE)
-                .unwrap()
-                .into_iter();
-        
-        let mut threads = Vec::new();
-        for device_id in devices {
-            {
-                let device = device::Device::new(device_id);
-                if device.dev_type().unwrap() == device::CL_DEVICE_TYPE_CPU {
-                    cpu_workers_num = 0;
-                }
-            }
-            let progress_bar = progress_bar.clone();
-            let basic_gen = basic_gen.clone();
-            let run = run.clone();
-            let cvar = cvar.clone();
-            let task = task.clone();
-            let device_ptr = device_id as usize;
-            let atlas_generator = atlas_generator.clone();
-            
-            threads.push(thread::spawn(move ||{
-                let context = Context::new(
-                    device_ptr as *mut core::ffi::c_void);
-
-                {
-                    progress_bar.lock().unwrap().print_info(
-                        "Info", 
-                        &format!(
-                            "OpenCL Worker: {}", 
-                            context.device_name),
-                        Color::Green,
-                        Style::Bold
-                    );
-                }
-                
-
-                let mut generate_sdf_task: Option<char> = None;
-                let mut generate_basic_task: Option<char> = None;
-
-                let mut basic_gen_buf = MonoImage::new(0, 0);
-                let mut basic_load_to_ocl_buf = MonoImage::new(0, 0);
-                let mut result_buf = MonoImage::new(0, 0);
-
-                let mut ocl_buf_load = None;
-                let mut ocl_buf_edge = None;
-                let mut ocl_buf_result = None;
-                let mut ocl_buf_len: usize = 0;
-
-                let mut str_buf = String::new();
-                
-                loop {
-
-                    {   // Test break condition
-                        let run =
-                            run.load(Ordering::Relaxed)
-                            || generate_basic_task.is_some()
-                            || generate_sdf_task.is_some();
-
-                        if !run {
-                            break;
-                        }
-                    }
-
-                    // Create SDF Task
-                    let sdf_task =  
-                        if let Some(ch) = generate_sdf_task {
-                            generate_sdf_task = None;
-
-                            let width = basic_load_to_ocl_buf.width;
-                            let height = basic_load_to_ocl_buf.height;
-
-                            let buffer_size = width * height;
-
-                            if ocl_buf_load.is_none() || ocl_buf_len < buffer_size {
-                                ocl_buf_len = buffer_size;
-                                ocl_buf_load = 
-                                    Some(memory::Buffer::<u8>::create(
-                                        &context.opencl_context,
-                                        memory::CL_MEM_WRITE_ONLY,
-                                        ocl_buf_len,
-                                        std::ptr::null_mut()
-                                    ).unwrap());
-
-                                ocl_buf_edge = 
-                                    Some(memory::Buffer::<u8>::create(
-                                        &context.opencl_context,
-                                        0,
-                                        ocl_buf_len,
-                                        std::ptr::null_mut()
-                                    ).unwrap());
-
-                                ocl_buf_result = 
-                                    Some(memory::Buffer::<u8>::create(
-                                        &context.opencl_context,
-                                        memory::CL_MEM_READ_ONLY,
-                                        ocl_buf_len / stride / stride,
-                                        std::ptr::null_mut()
-                                    ).unwrap());
-                            }
-
-                            let wait_load = 
-                                context.write_buffer_to_cl(
-                                    &basic_load_to_ocl_buf.pixels, 
-                                    ocl_buf_load.as_mut().unwrap(), 
-                                    &[]);
-
-                            let wait_edge_detect =
-                                context.edge_detect(
-                                    ocl_buf_load.as_ref().unwrap(),
-                                    ocl_buf_edge.as_mut().unwrap(),
-                                    width,
-                                    height,
-                                    &[wait_load]
-                                );
-
-                            let sdf_width = width / stride;
-                            let sdf_height = height / stride;
-
-                            let wait_sdf_generate =
-                                context.sdf_generate(
-                                    ocl_buf_edge.as_ref().unwrap(),
-                                    ocl_buf_result.as_mut().unwrap(),
-                                    width,
-                                    height,
-                                    sdf_width,
-                                    sdf_height,
-                                    stride,
-                                    search_radius,
-                                    &[wait_edge_detect]
-                                );
-
-                            Some((ch, wait_sdf_generate, sdf_width, sdf_height))
-                        } else {
-                            None
-                        };
-                    
-
-                    {   // Generate Basic Task
-                        if let Some(task) = generate_basic_task {
-                            generate_basic_task = None;
-                            str_buf.clear();
-                            str_buf.push(task);
-                            if basic_gen.generate(&str_buf, &mut basic_gen_buf) {
-                                generate_sdf_task = Some(task);
-                            } else {
-                                progress_bar
-                                    .lock()
-                                    .unwrap()
-                                    .print_info(
-                                        "Warning", 
-                                        &format!("Can not render {}", task), 
-                                        Color::Yellow, 
-                                        Style::Bold);
-                            }
-                        }
-                    }
-
-                    
-                    {   // Get next task
-                        let mut task = task.lock().unwrap();
-                        if let Some(ch) = *task {
-                            generate_basic_task = Some(ch);
-                            *task = None;
-                        }
-                        cvar.notify_one();
-                    }
-
-                    {   // Send Result
-                        if let Some((ch, event, sdf_width, sdf_height)) = sdf_task {
-                            result_buf.resize(sdf_width, sdf_height);
-                            context.read_buffer_to_cpu(
-                                ocl_buf_result.as_ref().unwrap(), 
-                                &mut result_buf.pixels, 
-                                &[event]).wait().unwrap();
-                            
-                            atlas_generator.lock().unwrap().push(ch, &result_buf);
-                        }
-                    }
-
-                    std::mem::swap(&mut basic_gen_buf, &mut basic_load_to_ocl_buf);
-                }
-            }));
+        result
+    })
+}
+
+/// Starts a font worker on every OpenCL device selected by the command
+/// line. Returns the workers and whether one of the devices is a CPU.
+#[cfg(feature = "opencl")]
+fn spawn_opencl_font_workers(
+    job: &Arc<FontJob>, 
+    args: &ArgMatches)
+    -> Result<(Vec<JoinHandle<Result<()>>>, bool)>
+{
+    let filter = DeviceFilter::from_args(args)?;
+    let devices = filter.devices()?;
+
+    if devices.is_empty() {
+        job.print_info(
+            "Warning", 
+            "Can not find OpenCL devices, rendering on CPU...",
+            Color::Yellow);
+    }
+
+    let mut has_cpu_device = false;
+    let mut threads = Vec::new();
+    for device_id in devices {
+        if device::Device::new(device_id).dev_type()? == device::CL_DEVICE_TYPE_CPU {
+            has_cpu_device = true;
         }
 
-        threads
-    };
+        let device_ptr = device_id as usize;
+        let worker_job = job.clone();
+        
+        threads.push(spawn_font_worker(job.clone(), move || {
+            let context = Context::new(
+                device_ptr as *mut core::ffi::c_void)?;
 
-    let opencl_workers: Vec<_> =
-        if enable_opencl_workers {
-            match platform::get_platforms() {
-                Err(_) => {
-                    progress_bar
-                        .lock()
-                        .unwrap()
-                        .print_info(
-                            "Warning", 
-                            "Can not get OpenCL platforms, rendering on CPU...",
-                            Color::Yellow, 
-                            Style::Bold);
-                    vec![]
-                },
-                Ok(x) => 
-                    x
-                        .into_iter()
-                        .flat_map(create_opencl_workers)
-                        .collect()
-            }
-                
-        } else {
-            vec![]
-        };
+            worker_job.print_info(
+                "Info", 
+                &format!("OpenCL Worker: {}", context.name()),
+                Color::Green);
 
-    cpu_workers_num = cpu_workers_num - (opencl_workers.len() as i32);
-    cpu_workers_num = cpu_workers_num.clamp(0, i32::MAX);
+            Ok(Box::new(context) as Box<dyn SdfBackend>)
+        }));
+    }
 
-    let cpu_workers : Vec<_> = 
-        (0 .. cpu_workers_num)
-            .into_iter()
-            .map(|_| {
-                let progress_bar = progress_bar.clone();
-                let basic_gen = basic_gen.clone();
-                let run = run.clone();
-                let cvar = cvar.clone();
-                let task = task.clone();
-                let atlas_generator = atlas_generator.clone();
-                
-                thread::spawn(move ||{
-                    let mut str_buf = String::new();
-                    let mut basic_gen_buf = MonoImage::new(0, 0);
-                    let mut edge_buf = MonoImage::new(0, 0);
-                    let mut sdf_buf = MonoImage::new(0, 0);
-
-                    loop {
-                        {   // Test break condition
-                            if !run.load(Ordering::Relaxed) {
-                                break;
-                            }
-                        }
-
-                        let mut generate_basic_task = None;
-
-                        {   // Get Next Task
-                            let mut task = task.lock().unwrap();
-                            if let Some(ch) = *task {
-                                generate_basic_task = Some(ch);
-                                *task = None;
-                            }
-                            cvar.notify_one();
-                        }
-
-                        if let Some(ch) = generate_basic_task{   
-                            // Do task
-                            str_buf.clear();
-                            str_buf.push(ch);
-                            if basic_gen.generate(&str_buf, &mut basic_gen_buf) {
-                                edge_buf.resize(basic_gen_buf.width, basic_gen_buf.height);
-                                basic_gen_buf.edge_detect(&mut edge_buf);
-                                sdf_buf.resize(
-                                    edge_buf.width / stride, 
-                                    edge_buf.height / stride);
-                                edge_buf.edge_generate_sdf(&mut sdf_buf, stride, search_radius);
-                                atlas_generator.lock().unwrap().push(ch, &sdf_buf);
-                            } else {
-                                progress_bar
-                                    .lock()
-                                    .unwrap()
-                                    .print_info(
-                                        "Warning", 
-                                        &format!("Can not render {}", ch), 
-                                        Color::Yellow, 
-                                        Style::Bold);
-                            }
-                        }
-                    }
-                })
-            })
-            .collect();
+    Ok((threads, has_cpu_device))
+}
 
+fn font(args: &ArgMatches) -> Result<()> {
+    let charset = CharsetRequest::from_args(args).get_charset()?;
 
-    progress_bar
-        .lock()
-        .unwrap()
-        .print_info(
-            "Info", 
-            &format!("OpenCL Workers: {}, CPU Workers: {}",
-                opencl_workers.len(), 
-                cpu_workers.len()),
-            Color::Green, 
-            Style::Bold);
-    
-    for i in charset {
-        let mut task = task.lock().unwrap();
-        while task.is_some() {
-            task = cvar.wait(task).unwrap();
-        }
+    let basic_gen = BasicFontGenerator::try_from(args)?;
+    let mode = basic_gen.mode;
+    let format: SampleFormat = parse_arg(args, "depth")?;
+    let options = get_sdf_options(args, Some(basic_gen.em_size()), None)?;
 
-        *task = Some(i);
+    if format == SampleFormat::F32 && mode.channels() == 4 {
+        return Err(Error::InvalidArgument(
+            "--depth 32 writes PFM pages, which can not hold MTSDF alpha".to_string()));
+    }
 
-        drop(task);
+    let mut atlas_generator = 
+        AtlasGenerator::new(
+            parse_arg(args, "page-width")?,
+            parse_arg(args, "page-height")?,
+            parse_arg(args, "OUTDIR")?,
+            parse_arg(args, "margin-x")?,
+            parse_arg(args, "margin-y")?,
+            mode,
+            format,
+            &options);
+
+    atlas_generator.add_font_metrics(&basic_gen.font_metrics());
+    atlas_generator.add_kerning(basic_gen.kerning(charset.iter().copied(), options.stride));
+
+    let job = Arc::new(FontJob {
+        basic_gen,
+        options,
+        progress_bar: Mutex::new(ProgressBar::new(charset.len())),
+        atlas_generator: Mutex::new(atlas_generator),
+        queue: WorkQueue::new(FONT_BATCH_SIZE),
+        done: AtomicUsize::new(0)
+    });
 
-        progress_bar.lock().unwrap().inc();
+    {
+        job.progress_bar
+            .lock()
+            .unwrap()
+            .set_action("Rendering", Color::LightCyan, Style::Bold);
     }
 
-    loop {
-        let mut task = task.lock().unwrap();
-        if task.is_some() {
-            task = cvar.wait(task).unwrap();
-            
-            if *task == None {
-                break;
-            }
+    #[cfg(feature = "opencl")]
+    let (opencl_workers, has_cpu_device) = 
+        if args.is_present("cpu") || mode != sdftool::SdfMode::Sdf {
+            (vec![], false)
         } else {
-            break;
-        }
-    }
+            spawn_opencl_font_workers(&job, args)?
+        };
 
-    run.store(false, Ordering::Release);
+    #[cfg(not(feature = "opencl"))]
+    let (opencl_workers, has_cpu_device): (Vec<JoinHandle<Result<()>>>, _) = (vec![], false);
 
-    for i in opencl_workers {
-        i.join().unwrap();
+    let cpu_workers_num =
+        if args.is_present("cpu-workers") {
+            parse_arg(args, "cpu-workers")?
+        } else if has_cpu_device {
+            // The OpenCL driver already runs on every core.
+            0
+        } else {
+            num_cpus::get().saturating_sub(opencl_workers.len())
+        };
+
+    if cpu_workers_num == 0 && opencl_workers.is_empty() {
+        return Err(Error::InvalidArgument(
+            "No workers, --cpu-workers 0 needs an OpenCL device".to_string()));
     }
 
-    for i in cpu_workers {
-        i.join().unwrap();
+    let cpu_workers : Vec<_> = 
+        (0 .. cpu_workers_num)
+            .map(|_| 
+                spawn_font_worker(job.clone(), || 
+                    Ok(Box::new(CpuBackend::new()) as Box<dyn SdfBackend>)))
+            .collect();
+
+    job.print_info(
+        "Info", 
+        &format!("OpenCL Workers: {}, CPU Workers: {}",
+            opencl_workers.len(), 
+            cpu_workers.len()),
+        Color::Green);
+    
+    job.queue.extend(charset.into_iter().enumerate());
+    job.queue.close();
+
+    let mut result = Ok(());
+
+    for i in opencl_workers.into_iter().chain(cpu_workers) {
+        let worker_result = i.join().unwrap();
+        if result.is_ok() {
+            result = worker_result;
+        }
     }
 
-    let atlas_generator = atlas_generator.lock().unwrap();
-    atlas_generator.save_current_page();
-    atlas_generator.save_metadata();
+    result?;
 
-    progress_bar
+    let atlas_generator = job.atlas_generator.lock().unwrap();
+    atlas_generator.save_current_page()?;
+    atlas_generator.save_metadata()?;
+
+    job.progress_bar
         .lock()
         .unwrap()
         .print_final_info(
@@ -540,185 +535,295 @@ fn font(args: &ArgMatches) {
             "", 
             Color::Green, 
             Style::Bold);
+
+    Ok(())
 }
 
-fn show_cl_devices() {
+#[cfg(feature = "opencl")]
+fn show_cl_devices() -> Result<()> {
     let platforms = 
         match platform::get_platforms() {
-            Ok(x) if x.len() == 0 => {
+            Ok(x) if x.is_empty() => {
                 println!("Can not get OpenCL platforms.");
-                return;
+                return Ok(());
             },
             Err(_) => {
                 println!("Can not get OpenCL platforms.");
-                return;
+                return Ok(());
             },
             Ok(x) => x
         };
 
-    let mut platform_id = 0;
-
-    for platform in platforms {
+    for (platform_id, platform) in platforms.into_iter().enumerate() {
         println!(
             "Platform {}: {}", 
             platform_id, 
-            platform.name().expect("Can not get platform."));
+            platform.name()?);
 
-        platform_id += 1;
+        let devices = platform.get_devices(DEVICE_TYPE)?;
 
-        let devices = 
-            platform.get_devices(DEVICE_TYPE)
-                .expect("Can not get devices.");
-                
-        let mut device_id = 0;
-
-        for device in devices {
+        for (device_id, device) in devices.into_iter().enumerate() {
             let device = device::Device::new(device);
 
             println!("    {}. {} ({})", 
                 device_id,
-                device.name().unwrap(), 
-                device::device_type_text(
-                    device.dev_type().unwrap()));
-
-            device_id += 1;
+                device.name()?, 
+                device::device_type_text(device.dev_type()?));
         }
     }
+
+    Ok(())
 }
 
-fn get_stride_and_search_radius(matches: &clap::ArgMatches) -> (usize, usize) {
-    let stride = 
-        matches.value_of("stride").unwrap().parse().unwrap();
+/// `em_size` is the size of an em in pixels of the source image, for
+/// spreads given in em units. `scale` replaces the stride by 1 and any
+/// ratio of source pixels to output pixels, for resampling afterwards.
+fn get_sdf_options(
+    matches: &clap::ArgMatches, 
+    em_size: Option<f32>, 
+    scale: Option<f32>) 
+    -> Result<SdfOptions> 
+{
+    let stride = if scale.is_some() { 1 } else { parse_stride(matches)? };
+    let scale = scale.unwrap_or(stride as f32);
+
+    let spread = |name| -> Result<Option<f32>> {
+        match matches.value_of(name) {
+            Some(_) => 
+                Ok(Some(parse_arg::<Spread>(matches, name)?.to_source_pixels(scale, em_size)?)),
+            None => Ok(None)
+        }
+    };
 
-    let search_radius = 
-        matches.value_of("search-radius").unwrap().parse().unwrap();
+    let encoding = DistanceEncoding {
+        inside_spread: spread("inside-spread")?,
+        outside_spread: spread("outside-spread")?,
+        zero_level: parse_arg(matches, "zero-level")?,
+        curve: parse_arg(matches, "curve")?
+    };
 
-    if stride <= 0 {
-        panic!("Stride must greate or equals 1.")
+    // Distances are only measured out to the search radius, so it grows to
+    // the largest spread.
+    let search_radius = encoding.search_radius(parse_search_radius(matches)?);
+    encoding.validate(search_radius)?;
+
+    Ok(SdfOptions { 
+        stride, 
+        search_radius,
+        algorithm: parse_arg(matches, "algorithm")?,
+        filter: parse_arg(matches, "filter")?,
+        anti_aliased: matches.is_present("anti-aliased"),
+        encoding
+    })
+}
+
+/// The `--threads` of the symbol command, with 0 replaced by the number of
+/// cores.
+fn get_cpu_threads(matches: &clap::ArgMatches) -> Result<usize> {
+    match parse_arg(matches, "threads")? {
+        0 => Ok(num_cpus::get()),
+        threads => Ok(threads)
     }
+}
 
-    (stride, search_radius)
+#[cfg(not(feature = "opencl"))]
+fn create_symbol_backend(matches: &clap::ArgMatches) -> Result<Box<dyn SdfBackend>> {
+    Ok(Box::new(CpuBackend::with_threads(get_cpu_threads(matches)?)))
 }
 
-fn symbol_cpu(matches: &clap::ArgMatches) {
-    println!("Info: Rendering on CPU.");
-    
-    let image =
-        MonoImage::load_from_file(
-            matches.value_of("INPUT").expect("No input png given."));
+#[cfg(feature = "opencl")]
+fn create_symbol_backend(matches: &clap::ArgMatches) -> Result<Box<dyn SdfBackend>> {
+    let threads = get_cpu_threads(matches)?;
+    let cpu = || Ok(Box::new(CpuBackend::with_threads(threads)) as Box<dyn SdfBackend>);
 
-    let mut edge = MonoImage::new(image.width, image.height);
-    image.edge_detect(&mut edge);
+    if matches.is_present("cpu") {
+        return cpu();
+    }
 
-    let (stride, search_radius) = 
-        get_stride_and_search_radius(&matches);
+    let filter = DeviceFilter::from_args(matches)?;
 
-    let mut result_sdf = 
-        MonoImage::new(edge.width / stride, edge.height / stride);
+    let mut context =
+        match filter.devices()?.into_iter().next() {
+            Some(device_id) => Context::new(device_id)?,
+            None => return cpu()
+        };
 
-    edge.edge_generate_sdf(&mut result_sdf, stride, search_radius);
+    context.host_threads = threads;
+    Ok(Box::new(context))
+}
 
-    result_sdf.save_png(&Path::new(
-        matches
-            .value_of("OUTPUT")
-            .expect("Output path not given.")));
+/// The OpenCL devices selected by `--platform-id`, `--device-id`,
+/// `--device-name` and `--device-type`.
+#[cfg(feature = "opencl")]
+struct DeviceFilter {
+    platform_id: Option<usize>,
+    device_id: Option<usize>,
+    name: Option<String>,
+    device_type: types::cl_device_type
 }
 
-fn symbol(matches: &clap::ArgMatches) {
-    if matches.is_present("cpu") {
-        return symbol_cpu(matches);
+#[cfg(feature = "opencl")]
+impl DeviceFilter {
+    fn from_args(matches: &ArgMatches) -> Result<Self> {
+        let index = |name| -> Result<Option<usize>> {
+            match matches.value_of(name) {
+                Some(_) => Ok(Some(parse_arg(matches, name)?)),
+                None => Ok(None)
+            }
+        };
+
+        let device_type =
+            match matches.value_of("device-type") {
+                Some("gpu") => device::CL_DEVICE_TYPE_GPU,
+                Some("cpu") => device::CL_DEVICE_TYPE_CPU,
+                Some("accelerator") => device::CL_DEVICE_TYPE_ACCELERATOR,
+                _ => device::CL_DEVICE_TYPE_ALL
+            };
+
+        Ok(Self {
+            platform_id: index("platform-id")?,
+            device_id: index("device-id")?,
+            name: matches.value_of("device-name").map(str::to_lowercase),
+            device_type
+        })
     }
 
-    let platform =
-        match platform::get_platforms() {
-            Ok(x) if x.len() == 0 => return symbol_cpu(matches),
-            Err(_) => return symbol_cpu(matches),
-            Ok(x) => 
-                x
-                    .into_iter()
-                    .nth(matches.value_of("platform-id").unwrap().parse::<usize>().unwrap())
-                    .expect("Can not get the platform.")
-        };
-            
+    /// Whether the filter excludes any device.
+    fn is_set(&self) -> bool {
+        self.platform_id.is_some() 
+            || self.device_id.is_some() 
+            || self.name.is_some() 
+            || self.device_type != device::CL_DEVICE_TYPE_ALL
+    }
+
+    /// The matching devices of all platforms, with the indices listed by
+    /// `sdftool cl-devices`.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the filter excludes every
+    /// device, and no devices without a filter if there is no OpenCL.
+    fn devices(&self) -> Result<Vec<types::cl_device_id>> {
+        let platforms = platform::get_platforms().unwrap_or_default();
+        let mut devices = Vec::new();
+
+        for (platform_id, platform) in platforms.into_iter().enumerate() {
+            if self.platform_id.is_some_and(|x| x != platform_id) {
+                continue;
+            }
+
+            for (device_id, id) in platform.get_devices(DEVICE_TYPE)?.into_iter().enumerate() {
+                let device = device::Device::new(id);
+
+                let matches = 
+                    self.device_id.is_none_or(|x| x == device_id)
+                        && device.dev_type()? & self.device_type != 0
+                        && match &self.name {
+                            Some(name) => device.name()?.to_lowercase().contains(name),
+                            None => true
+                        };
+
+                if matches {
+                    devices.push(id);
+                }
+            }
+        }
 
-    let device_id =
-        matches.value_of("device-id").unwrap().parse::<usize>().unwrap();
+        if devices.is_empty() && self.is_set() {
+            return Err(Error::InvalidArgument(
+                "No OpenCL device matches --platform-id, --device-id, --device-name and --device-type"
+                    .to_string()));
+        }
+
+        Ok(devices)
+    }
+}
+
+/// An image size given as `WxH` on the command line.
+#[derive(Clone, Copy)]
+struct Size(usize, usize);
 
-    let devices =
-        platform
-        .get_devices(DEVICE_TYPE)
-        .unwrap();
+impl FromStr for Size {
+    type Err = Error;
 
-    if devices.len() == 0 {
-        return symbol_cpu(matches);
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgument(format!("Invalid size {:?}, expected WxH", s));
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+        let width: usize = width.trim().parse().map_err(|_| invalid())?;
+        let height: usize = height.trim().parse().map_err(|_| invalid())?;
+
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Size(width, height))
     }
+}
 
-    let context = 
-        crate::context::Context::new(
-            devices
-                .into_iter()
-                .nth(device_id)
-                .unwrap());
-
-    let (image, width, height) =
-        context.load_png(matches.value_of("INPUT").expect("No input png given."));
-
-    let mut edge = 
-        memory::Buffer::<u8>::create(
-            &context.opencl_context,
-            0,
-            width * height,
-            std::ptr::null_mut()
-        ).unwrap();
-
-    let wait_for_edge_detect =
-        context.edge_detect(
-            &image, 
-            &mut edge, 
-            width, 
-            height,
-            &[]);
-
-    let (stride, search_radius) = 
-        get_stride_and_search_radius(&matches);
-
-    let mut result_sdf = 
-        MonoImage::new(width / stride, height / stride);
-
-    let mut gpu_sdf =
-        memory::Buffer::<u8>::create(
-            &context.opencl_context,
-            memory::CL_MEM_READ_ONLY,
-            result_sdf.width * result_sdf.height,
-            std::ptr::null_mut()
-        ).unwrap();
-
-    let wait_for_sdf_generate =
-        context.sdf_generate(
-            &edge,
-            &mut gpu_sdf,
-            width,
-            height,
-            result_sdf.width,
-            result_sdf.height,
-            stride,
-            search_radius,
-            &[wait_for_edge_detect]
-        );
+fn symbol(matches: &clap::ArgMatches) -> Result<()> {
+    let mut backend = create_symbol_backend(matches)?;
+    println!("Info: Rendering on {}.", backend.name());
 
-    
+    let input = matches.value_of("INPUT").unwrap();
+    let output = Path::new(matches.value_of("OUTPUT").unwrap());
+    let depth = parse_arg(matches, "depth")?;
+    let tile_size = parse_arg(matches, "tile-size")?;
 
-    let wait_for_read_buffer =
-        context.read_buffer_to_cpu(
-            &gpu_sdf, 
-            &mut result_sdf.pixels, 
-            &[wait_for_sdf_generate]);
+    let output_size: Option<Size> = 
+        match matches.value_of("output-size") {
+            Some(_) => Some(parse_arg(matches, "output-size")?),
+            None => None
+        };
+
+    // Resampling needs all distances, other sizes stream the files.
+    let Size(width, height) =
+        match output_size {
+            Some(x) => x,
+            None => {
+                let options = get_sdf_options(matches, None, None)?;
+                sdftool::generate_sdf_streamed(
+                    backend.as_mut(), 
+                    input, 
+                    output, 
+                    depth, 
+                    &options, 
+                    tile_size)?;
+
+                return save_symbol_info(output, depth, &options, options.stride as f32);
+            }
+        };
+
+    let image = MonoImage::load_with(input, backend.as_mut())?;
 
-    wait_for_read_buffer.wait().unwrap();
+    let scale = 
+        (image.width as f32 / width as f32 + image.height as f32 / height as f32) / 2.0;
 
-    result_sdf.save_png(&Path::new(
-        matches
-            .value_of("OUTPUT")
-            .expect("Output path not given.")));
+    let options = get_sdf_options(matches, None, Some(scale))?;
+
+    let mut result_sdf = MonoImage::new(0, 0);
+    sdftool::generate_sdf_tiled(
+        backend.as_mut(), 
+        &image, 
+        &mut result_sdf, 
+        &options, 
+        tile_size)?;
+
+    result_sdf
+        .resample(width, height, options.filter)
+        .save_distances(output, depth, &options)?;
+
+    save_symbol_info(output, depth, &options, scale)
 }
 
+/// Writes the depth and distance encoding of a symbol next to `output`, as
+/// `<name>.info.csv` like the `info.csv` of font atlases. `scale` is the
+/// size of an output pixel in pixels of the source image.
+fn save_symbol_info(
+    output: &Path, 
+    depth: SampleFormat, 
+    options: &SdfOptions, 
+    scale: f32) 
+    -> Result<()> 
+{
+    let mut info = vec![("depth".to_string(), depth.to_string())];
+    info.extend(options.encoding.info(options.search_radius, scale));
+    sdftool::save_info(&output.with_extension("info.csv"), &info)
+}
@@ -1,137 +1,675 @@
+use std::fmt;
 use std::path::Path;
 use std::fs::File;
+use std::io::Write;
+use std::str::FromStr;
 
 use png::OutputInfo;
+use crate::backend::{CpuBackend, SdfAlgorithm, SdfBackend, SdfFilter, SdfOptions};
+use crate::error::{Error, Result};
 use crate::program_cpu::*;
+use crate::program_simd;
 
-pub struct MonoImage {
-    pub pixels: Vec<u8>,
-    pub width: usize,
-    pub height: usize
+/// A channel value of a [`MonoImage`].
+pub trait Sample: Copy + Default + Send + Sync + 'static {
+    /// Quantizes a level of a [`crate::DistanceEncoding`], which is `t` of
+    /// the way from `zero` up to 1.0 for positive `t`, or down to 0.0 for
+    /// negative `t`. The level is truncated towards `zero`.
+    fn from_level(zero: f32, t: f32) -> Self;
 }
 
-impl MonoImage {
-    pub fn new(width: usize, height: usize) -> Self {
-        Self { 
-            pixels: vec![0; width * height].into(),
-            width,
-            height
+/// Quantizes a level to `0..=max`, see [`Sample::from_level`].
+fn quantize_level(zero: f32, t: f32, max: i32) -> i32 {
+    let zero = (zero * max as f32).round() as i32;
+    let range = if t < 0.0 { zero } else { max - zero };
+
+    zero + (t * range as f32) as i32
+}
+
+impl Sample for u8 {
+    /// Maps levels to `0..=254`, so that level 0.5 is exactly 127.
+    fn from_level(zero: f32, t: f32) -> Self {
+        quantize_level(zero, t, 254) as u8
+    }
+}
+
+impl Sample for u16 {
+    /// Maps levels to `0..=65534`, so that level 0.5 is exactly 32767.
+    fn from_level(zero: f32, t: f32) -> Self {
+        quantize_level(zero, t, 65534) as u16
+    }
+}
+
+impl Sample for f32 {
+    /// Keeps the level as it is.
+    fn from_level(zero: f32, t: f32) -> Self {
+        if t < 0.0 { zero + t * zero } else { zero + t * (1.0 - zero) }
+    }
+}
+
+/// The precision distance fields are saved with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 8-bit PNG.
+    U8,
+
+    /// 16-bit PNG.
+    U16,
+
+    /// 32-bit float PFM (portable float map) holding the encoded level.
+    F32
+}
+
+impl SampleFormat {
+    /// The file extension of the format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            SampleFormat::U8 | SampleFormat::U16 => "png",
+            SampleFormat::F32 => "pfm"
         }
     }
+}
 
-    pub fn load_from_file(png: &str) -> Self {
-        let (frame_info, mut buf) = Self::load_png_pixels(png);
-    
-        buf.resize(frame_info.buffer_size(), 0);
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleFormat::U8 => write!(f, "8"),
+            SampleFormat::U16 => write!(f, "16"),
+            SampleFormat::F32 => write!(f, "32")
+        }
+    }
+}
 
-        let strip = |buf: &[u8], stride| {
-            let mut img = 
-                MonoImage::new(
-                    frame_info.width as usize, 
-                    frame_info.height as usize);
-            
-            rgba_to_grayscale(&buf, &mut img.pixels, stride);
-            img
-        };
-    
-        match frame_info.color_type {
-            | png::ColorType::Grayscale 
-            | png::ColorType::GrayscaleAlpha => 
-                Self { 
-                    pixels: buf,
-                    width: frame_info.width as usize,
-                    height: frame_info.height as usize
-                },
-            | png::ColorType::Rgb => strip(&buf, 3),
-            | png::ColorType::Rgba => strip(&buf, 4),
-            | _ => panic!("PNG frame must in grayscale/rgb type.")
+impl FromStr for SampleFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "8" => Ok(SampleFormat::U8),
+            "16" => Ok(SampleFormat::U16),
+            "32" => Ok(SampleFormat::F32),
+            _ => Err(Error::InvalidArgument(format!("Unknown sample depth {:?}", s)))
+        }
+    }
+}
+
+/// An image stored row by row, grayscale unless created with
+/// [`MonoImage::with_channels`] for multi-channel SDFs.
+///
+/// Loaded images and edge images have 8-bit samples. Distance fields are
+/// generated as `f32` distances normalized to `-1.0..=1.0` and encoded with
+/// [`MonoImage::encode`] when saved.
+#[derive(Clone)]
+pub struct MonoImage<T = u8> {
+    pub pixels: Vec<T>,
+    pub width: usize,
+    pub height: usize,
+
+    /// Interleaved channels of every pixel, 1 for grayscale, 3 for RGB or 4
+    /// for RGBA.
+    pub channels: usize
+}
+
+impl<T: Sample> MonoImage<T> {
+    /// Creates a black grayscale image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::with_channels(width, height, 1)
+    }
+
+    /// Creates a black image with `channels` channels per pixel.
+    pub fn with_channels(width: usize, height: usize, channels: usize) -> Self {
+        Self { 
+            pixels: vec![T::default(); width * height * channels],
+            width,
+            height,
+            channels
         }
     }
 
+    /// Returns the index of a pixel, clamping the position to the image.
+    /// Its first channel is at `offset * channels` in `pixels`. Empty images
+    /// have no pixels, the index is 0 for them.
     pub fn offset(&self, x: usize, y: usize) -> usize {
-        let x = x.clamp(0, self.width - 1);
-        let y = y.clamp(0, self.height - 1);
+        let x = x.min(self.width.saturating_sub(1));
+        let y = y.min(self.height.saturating_sub(1));
 
         y * self.width + x
     }
 
-    pub fn set_pixel(&mut self, x: usize, y: usize, p: u8) {
+    pub fn set_pixel(&mut self, x: usize, y: usize, p: T) {
         let offset = self.offset(x, y);
         self.pixels[offset] = p;
     }
 
     pub fn resize(&mut self, width: usize, height: usize) {
-        self.pixels.resize(width * height, 0);
+        self.pixels.resize(width * height * self.channels, T::default());
         self.width = width;
         self.height = height;
     }
 
     pub fn clear_color(&mut self) {
+        self.fill(T::default());
+    }
+
+    /// Copies the `width` x `height` pixels at `x`, `y` into a new image.
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> MonoImage<T> {
+        let mut to = MonoImage::with_channels(width, height, self.channels);
+        to.paste(self, x, y, 0, 0, width, height);
+        to
+    }
+
+    /// Copies the `width` x `height` pixels at `src_x`, `src_y` of `src`
+    /// to `x`, `y` of this image.
+    #[allow(clippy::too_many_arguments)]
+    pub fn paste(
+        &mut self, 
+        src: &MonoImage<T>, 
+        src_x: usize, 
+        src_y: usize, 
+        x: usize, 
+        y: usize, 
+        width: usize, 
+        height: usize) 
+    {
+        assert!(src.channels == self.channels);
+        assert!(src_x + width <= src.width && src_y + height <= src.height);
+        assert!(x + width <= self.width && y + height <= self.height);
+
+        let row = width * self.channels;
+        for i in 0..height {
+            let from = ((src_y + i) * src.width + src_x) * self.channels;
+            let to = ((y + i) * self.width + x) * self.channels;
+            self.pixels[to .. to + row].copy_from_slice(&src.pixels[from .. from + row]);
+        }
+    }
+
+    /// Sets every channel of every pixel to `value`.
+    pub fn fill(&mut self, value: T) {
         for i in &mut self.pixels {
-            *i = 0;
+            *i = value;
         }
     }
 
-    pub fn save_png(&self, out: &Path) {
-        let output = File::create(out).unwrap();
-        let w = std::io::BufWriter::new(output);
-    
-        let mut enc = 
-            png::Encoder::new(w, self.width as u32, self.height as u32);
-    
-        enc.set_color(png::ColorType::Grayscale);
-        enc.set_depth(png::BitDepth::Eight);
+    fn png_color_type(&self) -> png::ColorType {
+        match self.channels {
+            3 => png::ColorType::Rgb,
+            4 => png::ColorType::Rgba,
+            _ => png::ColorType::Grayscale
+        }
+    }
+}
+
+fn write_png(
+    out: &Path, 
+    width: usize, 
+    height: usize, 
+    color: png::ColorType, 
+    depth: png::BitDepth, 
+    data: &[u8]) 
+    -> Result<()>
+{
+    let output = File::create(out)?;
+    let w = std::io::BufWriter::new(output);
+
+    let mut enc = 
+        png::Encoder::new(w, width as u32, height as u32);
+
+    enc.set_color(color);
+    enc.set_depth(depth);
+
+    let mut writer = enc.write_header()?;
+    writer.write_image_data(data)?;
+    Ok(())
+}
+
+impl MonoImage<u8> {
+    /// Loads an 8-bit PNG file, converting RGB/RGBA images to grayscale by
+    /// taking their first channel.
+    pub fn load_from_file(png: &str) -> Result<Self> {
+        Self::load_with(png, &mut CpuBackend::new())
+    }
+
+    /// Same as [`MonoImage::load_from_file`], converting to grayscale with
+    /// `backend`.
+    pub fn load_with(png: &str, backend: &mut dyn SdfBackend) -> Result<Self> {
+        let (frame_info, mut buf) = Self::load_png_pixels(png)?;
     
-        let mut writer = enc.write_header().unwrap();
-        writer.write_image_data(&self.pixels).unwrap();
+        buf.resize(frame_info.buffer_size(), 0);
+
+        let mut img = 
+            MonoImage::new(
+                frame_info.width as usize, 
+                frame_info.height as usize);
+
+        let channels =
+            match frame_info.color_type {
+                | png::ColorType::Grayscale => 1,
+                | png::ColorType::GrayscaleAlpha => 2,
+                | png::ColorType::Rgb => 3,
+                | png::ColorType::Rgba => 4,
+                | color_type => 
+                    return Err(Error::UnsupportedColorType(color_type, frame_info.bit_depth))
+            };
+
+        if channels == 1 {
+            img.pixels = buf;
+        } else {
+            backend.grayscale(&buf, channels, &mut img)?;
+        }
+
+        Ok(img)
     }
 
-    pub fn edge_detect(&self, to: &mut MonoImage) {
+    /// Saves the image as an 8-bit grayscale, RGB or RGBA PNG file.
+    pub fn save_png(&self, out: &Path) -> Result<()> {
+        write_png(
+            out, 
+            self.width, 
+            self.height, 
+            self.png_color_type(), 
+            png::BitDepth::Eight, 
+            &self.pixels)
+    }
+
+    /// Marks pixels of the shape (`>= 128`) as edge (255) or inner (127)
+    /// pixels in `to`, outside pixels become 0. Runs on `threads` threads.
+    pub fn edge_detect(&self, to: &mut MonoImage, threads: usize) {
         assert!(to.width == self.width);
         assert!(to.height == self.height);
 
-        edge_detect(&self.pixels, &mut to.pixels, to.width, to.height);
+        program_simd::edge_detect(&self.pixels, &mut to.pixels, to.width, to.height, threads);
     }
 
-    pub fn edge_generate_sdf(&self, to: &mut MonoImage, stride: usize, search_radius: usize) {
-        assert!(to.width == self.width / stride);
-        assert!(to.height == self.height / stride);
+    /// Generates the signed distance field of an edge image created by
+    /// [`MonoImage::edge_detect`], downsampled by `options.stride` with
+    /// `options.filter`. Runs on `threads` threads.
+    pub fn edge_generate_sdf(
+        &self, 
+        to: &mut MonoImage<f32>, 
+        options: &SdfOptions, 
+        threads: usize) 
+    {
+        assert!(to.width == self.width / options.stride);
+        assert!(to.height == self.height / options.stride);
 
-        sdf_generate(
+        if options.filter == SdfFilter::Point || options.stride == 1 {
+            generate_distances(
+                options.algorithm,
+                &self.pixels, 
+                &mut to.pixels, 
+                self.width, 
+                self.height, 
+                to.width, 
+                to.height, 
+                options.stride, 
+                options.search_radius,
+                threads);
+            return;
+        }
+
+        // Filters reduce the squared distances at full resolution, like the
+        // OpenCL devices do.
+        let mut full = vec![0; self.width * self.height];
+        generate_distances(
+            options.algorithm,
             &self.pixels, 
-            &mut to.pixels, 
+            &mut full, 
+            self.width, 
+            self.height, 
+            self.width, 
+            self.height, 
+            1, 
+            options.search_radius,
+            threads);
+
+        let min_abs = options.filter == SdfFilter::MinAbs;
+        let mut reduced = vec![0; to.pixels.len()];
+        sdf_downsample_distances(
+            &full, 
+            &mut reduced, 
             self.width, 
             self.height, 
             to.width, 
             to.height, 
-            stride, 
-            search_radius)
+            options.stride, 
+            options.search_radius,
+            min_abs,
+            threads);
+
+        let samples = if min_abs { 1 } else { options.stride * options.stride };
+        decode_downsampled(&reduced, &mut to.pixels, samples, options.search_radius);
+    }
+
+    /// Generates the signed distance field of an anti-aliased grayscale
+    /// image, downsampled by `options.stride` with `options.filter`.
+    ///
+    /// Instead of thresholding the image at 128, the sub-pixel position of
+    /// the edge is estimated from the coverage and gradient of the pixels,
+    /// so a smaller origin scale gives the same quality. Runs on `threads`
+    /// threads.
+    pub fn anti_aliased_generate_sdf(
+        &self, 
+        to: &mut MonoImage<f32>, 
+        options: &SdfOptions, 
+        threads: usize) 
+    {
+        assert!(to.width == self.width / options.stride);
+        assert!(to.height == self.height / options.stride);
+
+        filter_sdf(to, self.width, self.height, options, threads, |sdf, sdf_width, sdf_height, stride| 
+            sdf_generate_anti_aliased(
+                &self.pixels, 
+                sdf, 
+                self.width, 
+                self.height, 
+                sdf_width, 
+                sdf_height, 
+                stride, 
+                options.search_radius,
+                threads))
     }
 
-    pub fn load_png_pixels(png: &str) -> (OutputInfo, Vec<u8>) {
+    /// Decodes the first frame of an 8-bit PNG file.
+    pub fn load_png_pixels(png: &str) -> Result<(OutputInfo, Vec<u8>)> {
         let input_png = 
-                png::Decoder::new(
-                    std::fs::File::open(png)
-                        .expect("Can not open the input file."));
+                png::Decoder::new(std::fs::File::open(png)?);
     
-        let mut png_info = 
-            input_png
-                .read_info()
-                .expect("Can not read information of png.");
+        let mut png_info = input_png.read_info()?;
     
         let mut buf = 
             vec![0; png_info.output_buffer_size()];
     
-        let frame_info = 
-            png_info
-                .next_frame(&mut buf)
-                .expect("Can not read frame from png.");
+        let frame_info = png_info.next_frame(&mut buf)?;
     
         if frame_info.bit_depth != png::BitDepth::Eight {
-            panic!("PNG Frame must in 8 bits.");
+            return Err(
+                Error::UnsupportedColorType(
+                    frame_info.color_type, 
+                    frame_info.bit_depth));
         }
     
-        (frame_info, buf)
+        Ok((frame_info, buf))
     }
 }
 
+/// Runs the CPU kernels of `algorithm`, see [`sdf_generate`].
+#[allow(clippy::too_many_arguments)]
+fn generate_distances<T: DistanceSample>(
+    algorithm: SdfAlgorithm,
+    edge: &[u8],
+    sdf: &mut [T],
+    edge_width: usize,
+    edge_height: usize,
+    sdf_width: usize,
+    sdf_height: usize,
+    stride: usize,
+    search_radius: usize,
+    threads: usize)
+{
+    let generate =
+        match algorithm {
+            SdfAlgorithm::RingSearch => program_simd::sdf_generate,
+            SdfAlgorithm::Exact => sdf_generate_exact,
+            SdfAlgorithm::JumpFlood => sdf_generate_jump_flood
+        };
+
+    generate(edge, sdf, edge_width, edge_height, sdf_width, sdf_height, stride, search_radius, threads)
+}
+
+/// Runs `generate(sdf, sdf_width, sdf_height, stride)` into `to` for
+/// [`SdfFilter::Point`], or at the full `width` x `height` of the source
+/// image and then downsamples the distances into `to` with `options.filter`.
+fn filter_sdf(
+    to: &mut MonoImage<f32>, 
+    width: usize, 
+    height: usize, 
+    options: &SdfOptions, 
+    threads: usize,
+    generate: impl FnOnce(&mut [f32], usize, usize, usize))
+{
+    if options.filter == SdfFilter::Point || options.stride == 1 {
+        generate(&mut to.pixels, to.width, to.height, options.stride);
+        return;
+    }
+
+    let mut full = vec![0.0; width * height];
+    generate(&mut full, width, height, 1);
+
+    sdf_downsample(
+        &full, 
+        &mut to.pixels, 
+        width, 
+        height, 
+        to.width, 
+        to.height, 
+        options.stride, 
+        options.filter == SdfFilter::MinAbs,
+        threads);
+}
+
+impl MonoImage<u16> {
+    /// Saves the image as a 16-bit grayscale, RGB or RGBA PNG file.
+    pub fn save_png(&self, out: &Path) -> Result<()> {
+        let data: Vec<u8> = 
+            self.pixels
+                .iter()
+                .flat_map(|x| x.to_be_bytes())
+                .collect();
+
+        write_png(
+            out, 
+            self.width, 
+            self.height, 
+            self.png_color_type(), 
+            png::BitDepth::Sixteen, 
+            &data)
+    }
+}
+
+impl MonoImage<f32> {
+    /// Encodes the normalized distances into samples of type `T` with
+    /// `options.encoding`.
+    pub fn encode<T: Sample>(&self, options: &SdfOptions) -> MonoImage<T> {
+        MonoImage {
+            pixels: 
+                self.pixels
+                    .iter()
+                    .map(|x| options.encoding.encode(*x, options.search_radius))
+                    .collect(),
+            width: self.width,
+            height: self.height,
+            channels: self.channels
+        }
+    }
+
+    /// Resamples the distances to `width` x `height` pixels with any ratio
+    /// to the current size, on the CPU.
+    ///
+    /// Every output pixel covers a rectangle of the image. [`SdfFilter::Point`]
+    /// interpolates the distance at its centre, the other filters reduce the
+    /// pixels centred in it like [`crate::program_cpu::sdf_downsample`], and
+    /// fall back to interpolation if it contains none.
+    pub fn resample(&self, width: usize, height: usize, filter: SdfFilter) -> MonoImage<f32> {
+        let mut to = MonoImage::with_channels(width, height, self.channels);
+        let scale_x = self.width as f32 / width as f32;
+        let scale_y = self.height as f32 / height as f32;
+
+        let sample = |x: i32, y: i32, channel: usize| 
+            self.pixels[self.offset(x.max(0) as usize, y.max(0) as usize) * self.channels + channel];
+
+        let interpolate = |x: f32, y: f32, channel: usize| {
+            let (x0, y0) = (x.floor(), y.floor());
+            let (fx, fy) = (x - x0, y - y0);
+            let (x0, y0) = (x0 as i32, y0 as i32);
+
+            (sample(x0, y0, channel) * (1.0 - fx) + sample(x0 + 1, y0, channel) * fx) * (1.0 - fy)
+                + (sample(x0, y0 + 1, channel) * (1.0 - fx) + sample(x0 + 1, y0 + 1, channel) * fx) * fy
+        };
+
+        // Pixels of the image whose centre lies in `pos * scale ..
+        // (pos + 1) * scale`.
+        let covered = |pos: usize, scale: f32, size: usize| {
+            let start = (pos as f32 * scale - 0.5).ceil().max(0.0) as usize;
+            let end = ((pos + 1) as f32 * scale - 0.5).ceil().max(0.0) as usize;
+            start .. end.min(size)
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let offset = (y * width + x) * self.channels;
+                let rows = covered(y, scale_y, self.height);
+                let columns = covered(x, scale_x, self.width);
+
+                for channel in 0..self.channels {
+                    let centre = 
+                        interpolate(
+                            (x as f32 + 0.5) * scale_x - 0.5, 
+                            (y as f32 + 0.5) * scale_y - 0.5, 
+                            channel);
+
+                    if filter == SdfFilter::Point || rows.is_empty() || columns.is_empty() {
+                        to.pixels[offset + channel] = centre;
+                        continue;
+                    }
+
+                    let mut sum = 0.0;
+                    let mut nearest = f32::INFINITY;
+
+                    for sy in rows.clone() {
+                        for sx in columns.clone() {
+                            let distance = sample(sx as i32, sy as i32, channel);
+                            sum += distance;
+
+                            if distance.abs() < nearest.abs() {
+                                nearest = distance;
+                            }
+                        }
+                    }
+
+                    to.pixels[offset + channel] =
+                        if filter == SdfFilter::MinAbs {
+                            nearest
+                        } else {
+                            sum / (rows.len() * columns.len()) as f32
+                        };
+                }
+            }
+        }
+
+        to
+    }
+
+    /// Saves the image as a grayscale or RGB PFM (portable float map) file.
+    pub fn save_pfm(&self, out: &Path) -> Result<()> {
+        let magic = 
+            match self.channels {
+                1 => "Pf",
+                3 => "PF",
+                channels => 
+                    return Err(Error::InvalidArgument(
+                        format!("PFM files can not hold {} channels", channels)))
+            };
+
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidArgument(
+                format!("Can not save the empty {}x{} image {}", self.width, self.height, out.display())));
+        }
+
+        let mut w = std::io::BufWriter::new(File::create(out)?);
+
+        // A negative scale marks little endian samples, rows go bottom to top.
+        write!(w, "{}\n{} {}\n-1.0\n", magic, self.width, self.height)?;
+
+        for row in self.pixels.chunks(self.width * self.channels).rev() {
+            for x in row {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Encodes the distances with `options.encoding` and saves them to
+    /// `out` in `format`.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the extension of `out` is not
+    /// the one of `format`.
+    pub fn save_distances(
+        &self, 
+        out: &Path, 
+        format: SampleFormat, 
+        options: &SdfOptions) 
+        -> Result<()> 
+    {
+        let extension = out.extension().and_then(|x| x.to_str()).unwrap_or("");
+        if !extension.eq_ignore_ascii_case(format.extension()) {
+            return Err(Error::InvalidArgument(
+                format!(
+                    "{}-bit samples are saved as .{} files, not {}", 
+                    format, 
+                    format.extension(), 
+                    out.display())));
+        }
+
+        match format {
+            SampleFormat::U8 => self.encode::<u8>(options).save_png(out),
+            SampleFormat::U16 => self.encode::<u16>(options).save_png(out),
+            SampleFormat::F32 => self.encode::<f32>(options).save_pfm(out)
+        }
+    }
+}
+
+#[test]
+fn test_save_empty_image() {
+    use crate::DistanceEncoding;
+
+    let options = SdfOptions {
+        stride: 8,
+        search_radius: 4,
+        algorithm: SdfAlgorithm::Exact,
+        filter: SdfFilter::Point,
+        anti_aliased: false,
+        encoding: DistanceEncoding::default()
+    };
+
+    let empty = MonoImage::<f32>::new(0, 0);
+    assert_eq!(empty.offset(3, 5), 0);
+
+    let dir = std::env::temp_dir();
+    for format in [SampleFormat::U8, SampleFormat::U16, SampleFormat::F32] {
+        let out = dir.join(format!("sdftool-empty.{}", format.extension()));
+        assert!(empty.save_distances(&out, format, &options).is_err());
+    }
+
+    let image = MonoImage::<f32>::new(2, 2);
+    let result = image.save_distances(&dir.join("sdftool-pfm.png"), SampleFormat::F32, &options);
+    assert!(matches!(result, Err(Error::InvalidArgument(_))));
+}
+
+#[test]
+fn test_resample() {
+    // Every row is the ramp `x - 2`, resampled by 2.5 and by 0.625.
+    let mut image = MonoImage::<f32>::new(5, 3);
+    for (i, pixel) in image.pixels.iter_mut().enumerate() {
+        *pixel = (i % 5) as f32 - 2.0;
+    }
+
+    let row = |image: &MonoImage<f32>| image.pixels[.. image.width].to_vec();
+
+    let point = image.resample(2, 2, SdfFilter::Point);
+    assert_eq!((point.width, point.height), (2, 2));
+    assert_eq!(row(&point), [-1.25, 1.25]);
+    assert_eq!(point.pixels[2 .. 4], point.pixels[.. 2]);
+
+    // The first output pixel covers the centres of two pixels, the second
+    // the ones of three.
+    assert_eq!(row(&image.resample(2, 2, SdfFilter::Box)), [-1.5, 1.0]);
+    assert_eq!(row(&image.resample(2, 2, SdfFilter::MinAbs)), [-1.0, 0.0]);
+
+    // The second output pixel covers no centre and is interpolated.
+    let larger = image.resample(8, 3, SdfFilter::Box);
+    assert_eq!(larger.pixels[0], -2.0);
+    assert_eq!(larger.pixels[1], -1.5625);
+    assert_eq!(larger.pixels[2], -1.0);
+}
@@ -0,0 +1,1046 @@
+use std::ops::{Add, Mul, Sub};
+
+use rusttype::OutlineBuilder;
+
+use crate::mono_image::MonoImage;
+
+/// A point or vector of an outline, in pixels with y pointing down.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    fn dot(self, other: Point) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    fn cross(self, other: Point) -> f64 {
+        self.x * other.y - self.y * other.x
+    }
+
+    fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    fn normalize(self) -> Point {
+        let length = self.length();
+        if length == 0.0 { self } else { self * (1.0 / length) }
+    }
+
+    fn mix(self, other: Point, t: f64) -> Point {
+        self + (other - self) * t
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f64> for Point {
+    type Output = Point;
+
+    fn mul(self, s: f64) -> Point {
+        Point::new(self.x * s, self.y * s)
+    }
+}
+
+/// Set of the MSDF channels an edge contributes to.
+pub type EdgeColor = u8;
+
+pub const RED: EdgeColor = 1;
+pub const GREEN: EdgeColor = 2;
+pub const BLUE: EdgeColor = 4;
+pub const YELLOW: EdgeColor = RED | GREEN;
+pub const MAGENTA: EdgeColor = RED | BLUE;
+pub const CYAN: EdgeColor = GREEN | BLUE;
+pub const WHITE: EdgeColor = RED | GREEN | BLUE;
+
+/// Distance to a segment, signed by the side of the segment, with the
+/// angle between the segment and the direction to it breaking ties.
+#[derive(Clone, Copy, Debug)]
+struct SignedDistance {
+    distance: f64,
+    dot: f64
+}
+
+impl SignedDistance {
+    const INFINITE: SignedDistance =
+        SignedDistance { distance: f64::NEG_INFINITY, dot: 1.0 };
+
+    fn is_closer_than(&self, other: &SignedDistance) -> bool {
+        let (a, b) = (self.distance.abs(), other.distance.abs());
+        a < b || (a == b && self.dot < other.dot)
+    }
+}
+
+fn non_zero_sign(x: f64) -> f64 {
+    if x > 0.0 { 1.0 } else { -1.0 }
+}
+
+/// Real roots of `a x^2 + b x + c`.
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 || b.abs() > 1e12 * a.abs() {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant > 0.0 {
+        let discriminant = discriminant.sqrt();
+        vec![(-b + discriminant) / (2.0 * a), (-b - discriminant) / (2.0 * a)]
+    } else if discriminant == 0.0 {
+        vec![-0.5 * b / a]
+    } else {
+        vec![]
+    }
+}
+
+/// Real roots of `a x^3 + b x^2 + c x + d`.
+fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a == 0.0 || (b / a).abs() >= 1e6 {
+        return solve_quadratic(b, c, d);
+    }
+
+    let (a, b, c) = (b / a, c / a, d / a);
+    let a2 = a * a;
+    let q = (a2 - 3.0 * b) / 9.0;
+    let r = (a * (2.0 * a2 - 9.0 * b) + 27.0 * c) / 54.0;
+    let r2 = r * r;
+    let q3 = q * q * q;
+    let a = a / 3.0;
+
+    if r2 < q3 {
+        let t = (r / q3.sqrt()).clamp(-1.0, 1.0).acos();
+        let q = -2.0 * q.sqrt();
+        let third = std::f64::consts::PI * 2.0 / 3.0;
+        vec![
+            q * (t / 3.0).cos() - a,
+            q * (t / 3.0 + third).cos() - a,
+            q * (t / 3.0 - third).cos() - a
+        ]
+    } else {
+        let u =
+            -non_zero_sign(r) * (r.abs() + (r2 - q3).sqrt()).powf(1.0 / 3.0);
+        let v = if u == 0.0 { 0.0 } else { q / u };
+
+        if u == v || (u - v).abs() < 1e-12 * (u + v).abs() {
+            vec![u + v - a, -0.5 * (u + v) - a]
+        } else {
+            vec![u + v - a]
+        }
+    }
+}
+
+/// A line, quadratic or cubic Bezier curve of an outline.
+#[derive(Clone, Copy, Debug)]
+pub enum Segment {
+    Line([Point; 2]),
+    Quad([Point; 3]),
+    Cubic([Point; 4])
+}
+
+impl Segment {
+    fn start(&self) -> Point {
+        match self {
+            Segment::Line(p) => p[0],
+            Segment::Quad(p) => p[0],
+            Segment::Cubic(p) => p[0]
+        }
+    }
+
+    fn end(&self) -> Point {
+        match self {
+            Segment::Line(p) => p[1],
+            Segment::Quad(p) => p[2],
+            Segment::Cubic(p) => p[3]
+        }
+    }
+
+    fn point(&self, t: f64) -> Point {
+        match self {
+            Segment::Line(p) => p[0].mix(p[1], t),
+            Segment::Quad(p) => p[0].mix(p[1], t).mix(p[1].mix(p[2], t), t),
+            Segment::Cubic(p) => {
+                let p12 = p[1].mix(p[2], t);
+                p[0].mix(p[1], t).mix(p12, t).mix(p12.mix(p[2].mix(p[3], t), t), t)
+            }
+        }
+    }
+
+    /// Tangent of the segment at `t`, not normalized.
+    fn direction(&self, t: f64) -> Point {
+        match self {
+            Segment::Line(p) => p[1] - p[0],
+            Segment::Quad(p) => {
+                let tangent = (p[1] - p[0]).mix(p[2] - p[1], t);
+                if tangent == Point::default() { p[2] - p[0] } else { tangent }
+            },
+            Segment::Cubic(p) => {
+                let tangent =
+                    (p[1] - p[0])
+                        .mix(p[2] - p[1], t)
+                        .mix((p[2] - p[1]).mix(p[3] - p[2], t), t);
+
+                if tangent != Point::default() {
+                    tangent
+                } else if t == 0.0 {
+                    p[2] - p[0]
+                } else if t == 1.0 {
+                    p[3] - p[1]
+                } else {
+                    tangent
+                }
+            }
+        }
+    }
+
+    /// Splits the segment at `t` with de Casteljau's algorithm.
+    fn split(&self, t: f64) -> (Segment, Segment) {
+        match self {
+            Segment::Line(p) => {
+                let m = p[0].mix(p[1], t);
+                (Segment::Line([p[0], m]), Segment::Line([m, p[1]]))
+            },
+            Segment::Quad(p) => {
+                let (a, b) = (p[0].mix(p[1], t), p[1].mix(p[2], t));
+                let m = a.mix(b, t);
+                (Segment::Quad([p[0], a, m]), Segment::Quad([m, b, p[2]]))
+            },
+            Segment::Cubic(p) => {
+                let (a, b, c) = (p[0].mix(p[1], t), p[1].mix(p[2], t), p[2].mix(p[3], t));
+                let (ab, bc) = (a.mix(b, t), b.mix(c, t));
+                let m = ab.mix(bc, t);
+                (Segment::Cubic([p[0], a, ab, m]), Segment::Cubic([m, bc, c, p[3]]))
+            }
+        }
+    }
+
+    fn split_in_thirds(&self) -> [Segment; 3] {
+        let (first, rest) = self.split(1.0 / 3.0);
+        let (second, third) = rest.split(0.5);
+        [first, second, third]
+    }
+
+    /// Signed distance from `origin` to the segment and the parameter of
+    /// the nearest point, which is outside of `0..=1` when an endpoint is
+    /// nearest and `origin` lies beyond it.
+    fn signed_distance(&self, origin: Point) -> (SignedDistance, f64) {
+        if let Segment::Line(p) = self {
+            let aq = origin - p[0];
+            let ab = p[1] - p[0];
+            let t = aq.dot(ab) / ab.dot(ab);
+            let eq = (if t > 0.5 { p[1] } else { p[0] }) - origin;
+            let endpoint_distance = eq.length();
+
+            if t > 0.0 && t < 1.0 {
+                let ortho_distance = aq.cross(ab) / ab.length();
+                if ortho_distance.abs() < endpoint_distance {
+                    return (SignedDistance { distance: ortho_distance, dot: 0.0 }, t);
+                }
+            }
+
+            let distance = non_zero_sign(aq.cross(ab)) * endpoint_distance;
+            let dot = ab.normalize().dot(eq.normalize()).abs();
+            return (SignedDistance { distance, dot }, t);
+        }
+
+        let (start, end) = (self.start(), self.end());
+        let qa = start - origin;
+
+        let start_direction = self.direction(0.0);
+        let mut min_distance = non_zero_sign(start_direction.cross(qa)) * qa.length();
+        let mut param = -qa.dot(start_direction) / start_direction.dot(start_direction);
+
+        let end_direction = self.direction(1.0);
+        let distance = (end - origin).length();
+        if distance < min_distance.abs() {
+            min_distance = non_zero_sign(end_direction.cross(end - origin)) * distance;
+            param = 1.0 + (origin - end).dot(end_direction) / end_direction.dot(end_direction);
+        }
+
+        match self {
+            Segment::Quad(p) => {
+                let ab = p[1] - p[0];
+                let br = p[2] - p[1] - ab;
+                let roots =
+                    solve_cubic(
+                        br.dot(br),
+                        3.0 * ab.dot(br),
+                        2.0 * ab.dot(ab) + qa.dot(br),
+                        qa.dot(ab));
+
+                for t in roots {
+                    if t > 0.0 && t < 1.0 {
+                        let qe = qa + ab * (2.0 * t) + br * (t * t);
+                        let distance = qe.length();
+                        if distance <= min_distance.abs() {
+                            min_distance = non_zero_sign((ab + br * t).cross(qe)) * distance;
+                            param = t;
+                        }
+                    }
+                }
+            },
+            Segment::Cubic(p) => {
+                let ab = p[1] - p[0];
+                let br = p[2] - p[1] - ab;
+                let as_ = p[3] - p[2] - (p[2] - p[1]) - br;
+                let at = |t: f64| qa + ab * (3.0 * t) + br * (3.0 * t * t) + as_ * (t * t * t);
+
+                // Newton iterations from a few starting points.
+                for start in 0..=4 {
+                    let mut t = start as f64 / 4.0;
+                    let mut qe = at(t);
+
+                    for _ in 0..4 {
+                        let d1 = ab * 3.0 + br * (6.0 * t) + as_ * (3.0 * t * t);
+                        let d2 = br * 6.0 + as_ * (6.0 * t);
+                        t -= qe.dot(d1) / (d1.dot(d1) + qe.dot(d2));
+                        if t <= 0.0 || t >= 1.0 {
+                            break;
+                        }
+
+                        qe = at(t);
+                        let distance = qe.length();
+                        if distance < min_distance.abs() {
+                            min_distance = non_zero_sign(d1.cross(qe)) * distance;
+                            param = t;
+                        }
+                    }
+                }
+            },
+            Segment::Line(_) => unreachable!()
+        }
+
+        let dot =
+            if (0.0..=1.0).contains(&param) {
+                0.0
+            } else if param < 0.5 {
+                start_direction.normalize().dot(qa.normalize()).abs()
+            } else {
+                end_direction.normalize().dot((end - origin).normalize()).abs()
+            };
+
+        (SignedDistance { distance: min_distance, dot }, param)
+    }
+
+    /// The distance to the tangent line at the nearest endpoint if
+    /// `origin` lies beyond it and that is nearer, otherwise `distance`.
+    fn pseudo_distance(&self, distance: SignedDistance, origin: Point, param: f64) -> SignedDistance {
+        let (base, direction, beyond) =
+            if param < 0.0 {
+                (self.start(), self.direction(0.0).normalize(), -1.0)
+            } else if param > 1.0 {
+                (self.end(), self.direction(1.0).normalize(), 1.0)
+            } else {
+                return distance;
+            };
+
+        let bq = origin - base;
+        if bq.dot(direction) * beyond > 0.0 {
+            let pseudo_distance = bq.cross(direction);
+            if pseudo_distance.abs() <= distance.distance.abs() {
+                return SignedDistance { distance: pseudo_distance, dot: 0.0 };
+            }
+        }
+
+        distance
+    }
+
+    /// Coefficients of the segment as a cubic polynomial in `t`, from the
+    /// constant term up.
+    fn power_basis(&self) -> [Point; 4] {
+        let zero = Point::default();
+        match self {
+            Segment::Line(p) => [p[0], p[1] - p[0], zero, zero],
+            Segment::Quad(p) => [p[0], (p[1] - p[0]) * 2.0, p[0] - p[1] * 2.0 + p[2], zero],
+            Segment::Cubic(p) => [
+                p[0],
+                (p[1] - p[0]) * 3.0,
+                (p[0] - p[1] * 2.0 + p[2]) * 3.0,
+                p[3] - p[0] + (p[1] - p[2]) * 3.0
+            ]
+        }
+    }
+
+    /// Appends the parameters in `0..1` where the segment crosses the line
+    /// from `a` to `b` to `params`.
+    fn intersections(&self, a: Point, b: Point, params: &mut Vec<f64>) {
+        let [c0, c1, c2, c3] = self.power_basis();
+        let ab = b - a;
+        let normal = Point::new(-ab.y, ab.x);
+
+        let roots = 
+            solve_cubic(c3.dot(normal), c2.dot(normal), c1.dot(normal), (c0 - a).dot(normal));
+
+        for t in roots {
+            if t > 0.0 && t < 1.0 {
+                let along = (self.point(t) - a).dot(ab) / ab.dot(ab);
+                if (0.0..=1.0).contains(&along) {
+                    params.push(t);
+                }
+            }
+        }
+    }
+
+    /// Bounding box of the control points, which contains the segment.
+    fn bounds(&self) -> (Point, Point) {
+        let points: &[Point] =
+            match self {
+                Segment::Line(p) => p,
+                Segment::Quad(p) => p,
+                Segment::Cubic(p) => p
+            };
+
+        points.iter().fold((points[0], points[0]), |(min, max), p| {
+            (Point::new(min.x.min(p.x), min.y.min(p.y)), Point::new(max.x.max(p.x), max.y.max(p.y)))
+        })
+    }
+
+    /// Appends points approximating the segment to `polygon`, without the
+    /// start point.
+    fn flatten(&self, polygon: &mut Vec<Point>) {
+        let hull_length =
+            match self {
+                Segment::Line(_) => {
+                    polygon.push(self.end());
+                    return;
+                },
+                Segment::Quad(p) => (p[1] - p[0]).length() + (p[2] - p[1]).length(),
+                Segment::Cubic(p) =>
+                    (p[1] - p[0]).length() + (p[2] - p[1]).length() + (p[3] - p[2]).length()
+            };
+
+        let pieces = (hull_length.ceil() as usize).clamp(1, 64);
+        for i in 1..=pieces {
+            polygon.push(self.point(i as f64 / pieces as f64));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    segment: Segment,
+    color: EdgeColor
+}
+
+/// The closed contours of a glyph, built by
+/// `rusttype::PositionedGlyph::build_outline`.
+#[derive(Clone, Debug, Default)]
+pub struct Outline {
+    contours: Vec<Vec<Edge>>,
+    offset: Point,
+    start: Point,
+    current: Point
+}
+
+impl Outline {
+    /// Creates an empty outline, translating every point added by the
+    /// `OutlineBuilder` methods by `offset`.
+    pub fn new(offset: Point) -> Self {
+        Self {
+            offset,
+            ..Self::default()
+        }
+    }
+
+    fn push(&mut self, segment: Segment, to: Point) {
+        if to != self.current {
+            if let Some(contour) = self.contours.last_mut() {
+                contour.push(Edge { segment, color: WHITE });
+            }
+        }
+
+        self.current = to;
+    }
+
+    /// Assigns colors to the edges so that the edges meeting at a corner
+    /// sharper than `angle_threshold` radians never share two channels,
+    /// following "Shape Decomposition for Multi-channel Distance Fields" by
+    /// Chlumsky.
+    pub fn color_edges(&mut self, angle_threshold: f64) {
+        let cross_threshold = angle_threshold.sin();
+
+        for contour in &mut self.contours {
+            let corners: Vec<usize> =
+                (0..contour.len())
+                    .filter(|i| {
+                        let previous =
+                            contour[(i + contour.len() - 1) % contour.len()]
+                                .segment
+                                .direction(1.0)
+                                .normalize();
+                        let next = contour[*i].segment.direction(0.0).normalize();
+
+                        previous.dot(next) <= 0.0 || previous.cross(next).abs() > cross_threshold
+                    })
+                    .collect();
+
+            match corners.len() {
+                0 => {
+                    for edge in contour.iter_mut() {
+                        edge.color = WHITE;
+                    }
+                },
+
+                // A teardrop, which needs at least three edges for the colors.
+                1 => {
+                    let colors = [CYAN, WHITE, MAGENTA];
+                    let corner = corners[0];
+
+                    if contour.len() < 3 {
+                        let mut parts: Vec<Segment> =
+                            contour
+                                .iter()
+                                .flat_map(|edge| edge.segment.split_in_thirds())
+                                .collect();
+
+                        parts.rotate_left(corner * 3);
+                        *contour =
+                            parts
+                                .into_iter()
+                                .map(|segment| Edge { segment, color: WHITE })
+                                .collect();
+                    }
+
+                    let (edges, corner) =
+                        (contour.len(), if contour.len() < 3 { 0 } else { corner });
+
+                    for i in 0..edges {
+                        let color =
+                            (2.0625 + 2.875 * i as f64 / (edges - 1) as f64) as usize - 2;
+                        contour[(corner + i) % edges].color = colors[color];
+                    }
+                },
+
+                _ => {
+                    let mut color = CYAN;
+                    let initial_color = color;
+                    let mut spline = 0;
+
+                    for i in 0..contour.len() {
+                        let index = (corners[0] + i) % contour.len();
+                        if spline + 1 < corners.len() && corners[spline + 1] == index {
+                            spline += 1;
+                            color =
+                                switch_color(
+                                    color,
+                                    if spline == corners.len() - 1 { initial_color } else { 0 });
+                        }
+
+                        contour[index].color = color;
+                    }
+                }
+            }
+        }
+    }
+
+    fn polygons(&self) -> Vec<Vec<Point>> {
+        self.contours
+            .iter()
+            .filter(|contour| !contour.is_empty())
+            .map(|contour| {
+                let mut polygon = vec![contour[0].segment.start()];
+                for edge in contour {
+                    edge.segment.flatten(&mut polygon);
+                }
+                polygon
+            })
+            .collect()
+    }
+
+    /// The edges split where they cross other contours, without the pieces
+    /// inside of other contours, so overlapping contours are measured by
+    /// the boundary of their union like the rasterized glyph.
+    fn boundary_edges(&self) -> Vec<Edge> {
+        let contours: Vec<_> = self.contours.iter().filter(|x| !x.is_empty()).collect();
+        if contours.len() < 2 {
+            return contours.into_iter().flatten().copied().collect();
+        }
+
+        let polygons = self.polygons();
+        let mut edges = Vec::new();
+        let mut params = Vec::new();
+
+        for (i, contour) in contours.iter().enumerate() {
+            let others: Vec<_> = 
+                polygons
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, polygon)| polygon.clone())
+                    .collect();
+
+            // A contour winds once around its inside, the sign of its area
+            // tells which way. Its own winding is `inner + 1` left of its
+            // edges and `inner` right of them.
+            let inner = if area(&polygons[i]) > 0.0 { 0 } else { -1 };
+
+            for edge in contour.iter() {
+                let (min, max) = edge.segment.bounds();
+                params.clear();
+
+                for polygon in &others {
+                    for (j, &a) in polygon.iter().enumerate() {
+                        let b = polygon[(j + 1) % polygon.len()];
+                        if a.x.max(b.x) >= min.x && a.x.min(b.x) <= max.x 
+                            && a.y.max(b.y) >= min.y && a.y.min(b.y) <= max.y 
+                        {
+                            edge.segment.intersections(a, b, &mut params);
+                        }
+                    }
+                }
+
+                params.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                params.dedup_by(|a, b| *a - *b < 1e-9);
+                params.push(1.0);
+
+                let (mut rest, mut start) = (edge.segment, 0.0);
+                for &t in &params {
+                    let (piece, next) = 
+                        if t < 1.0 { rest.split((t - start) / (1.0 - start)) } else { (rest, rest) };
+
+                    // The piece is on the boundary if exactly one side of
+                    // it is outside of every contour. The sides are sampled
+                    // off the piece, as other contours may run along it.
+                    let middle = piece.point(0.5);
+                    let direction = piece.direction(0.5).normalize();
+                    let left = middle + Point::new(-direction.y, direction.x) * SIDE_OFFSET;
+                    let right = middle + Point::new(direction.y, -direction.x) * SIDE_OFFSET;
+
+                    let left = winding(&crossings(&others, left.y), left.x) + inner + 1;
+                    let right = winding(&crossings(&others, right.y), right.x) + inner;
+                    if (left == 0) != (right == 0) {
+                        edges.push(Edge { segment: piece, color: edge.color });
+                    }
+
+                    rest = next;
+                    start = t;
+                }
+            }
+        }
+
+        edges
+    }
+
+    /// Generates the multi-channel signed distance field of the outline
+    /// into the RGB image `msdf`. RGBA images additionally get the true
+    /// signed distance in alpha (MTSDF).
+    ///
+    /// Output pixels sample the outline at the centre of `stride` x
+    /// `stride` blocks, and distances are normalized to `search_radius`
+    /// pixels of the outline like [`crate::program_cpu::sdf_generate`].
+    pub fn generate_msdf(&self, msdf: &mut MonoImage<f32>, stride: usize, search_radius: usize) {
+        assert!(msdf.channels == 3 || msdf.channels == 4);
+
+        let polygons = self.polygons();
+        let edges = self.boundary_edges();
+        let edges = edges_with_bounds(&edges);
+        let mut distances = vec![[0.0; 3]; msdf.width * msdf.height];
+        let mut true_distances = vec![0.0; msdf.width * msdf.height];
+
+        for y in 0..msdf.height {
+            let crossings = crossings(&polygons, sample_point(0, y, stride).y);
+
+            for x in 0..msdf.width {
+                let origin = sample_point(x, y, stride);
+                let mut nearest = [(SignedDistance::INFINITE, None, 0.0); 3];
+                let mut true_nearest = SignedDistance::INFINITE;
+
+                for (edge, bounds) in &edges {
+                    let farthest_needed =
+                        nearest
+                            .iter()
+                            .enumerate()
+                            .filter(|(channel, _)| edge.color & (1 << channel) != 0)
+                            .map(|(_, nearest)| nearest.0.distance.abs())
+                            .fold(true_nearest.distance.abs(), f64::max);
+
+                    if bounds_distance(*bounds, origin) > farthest_needed {
+                        continue;
+                    }
+
+                    let (distance, param) = edge.segment.signed_distance(origin);
+
+                    for (channel, nearest) in nearest.iter_mut().enumerate() {
+                        if edge.color & (1 << channel) != 0 && distance.is_closer_than(&nearest.0) {
+                            *nearest = (distance, Some(edge.segment), param);
+                        }
+                    }
+
+                    if distance.is_closer_than(&true_nearest) {
+                        true_nearest = distance;
+                    }
+                }
+
+                let pixel = &mut distances[y * msdf.width + x];
+                for (channel, (distance, segment, param)) in nearest.iter().enumerate() {
+                    pixel[channel] =
+                        match segment {
+                            Some(segment) => segment.pseudo_distance(*distance, origin, *param).distance,
+                            None => distance.distance
+                        };
+                }
+
+                // Contours may run either way, the winding tells the inside.
+                let inside = winding(&crossings, origin.x) != 0;
+                if (median(*pixel) > 0.0) != inside {
+                    for distance in pixel.iter_mut() {
+                        *distance = -*distance;
+                    }
+                }
+
+                let true_distance = true_nearest.distance.abs();
+                true_distances[y * msdf.width + x] = 
+                    if inside { true_distance } else { -true_distance };
+            }
+        }
+
+        correct_clashes(&mut distances, msdf.width, msdf.height, 1.001 * stride as f64);
+
+        let channels = msdf.channels;
+        for (i, pixel) in msdf.pixels.chunks_mut(channels).enumerate() {
+            for channel in 0..3 {
+                pixel[channel] = normalize_distance(distances[i][channel], search_radius);
+            }
+
+            if channels == 4 {
+                pixel[3] = normalize_distance(true_distances[i], search_radius);
+            }
+        }
+    }
+
+    /// Generates the single-channel signed distance field of the outline
+    /// into the grayscale image `sdf`, sampled like
+    /// [`Outline::generate_msdf`]. The inside is given by the non-zero
+    /// winding rule.
+    pub fn generate_sdf(&self, sdf: &mut MonoImage<f32>, stride: usize, search_radius: usize) {
+        assert!(sdf.channels == 1);
+
+        let polygons = self.polygons();
+        let edges = self.boundary_edges();
+        let edges = edges_with_bounds(&edges);
+
+        for y in 0..sdf.height {
+            let crossings = crossings(&polygons, sample_point(0, y, stride).y);
+
+            for x in 0..sdf.width {
+                let origin = sample_point(x, y, stride);
+                let mut distance = f64::INFINITY;
+
+                for (edge, bounds) in &edges {
+                    if bounds_distance(*bounds, origin) <= distance {
+                        distance = 
+                            distance.min(edge.segment.signed_distance(origin).0.distance.abs());
+                    }
+                }
+
+                let distance = 
+                    if winding(&crossings, origin.x) != 0 { distance } else { -distance };
+
+                sdf.pixels[y * sdf.width + x] = normalize_distance(distance, search_radius);
+            }
+        }
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let to = Point::new(x as f64, y as f64) + self.offset;
+        self.contours.push(Vec::new());
+        self.start = to;
+        self.current = to;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = Point::new(x as f64, y as f64) + self.offset;
+        self.push(Segment::Line([self.current, to]), to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let control = Point::new(x1 as f64, y1 as f64) + self.offset;
+        let to = Point::new(x as f64, y as f64) + self.offset;
+        self.push(Segment::Quad([self.current, control, to]), to);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let control1 = Point::new(x1 as f64, y1 as f64) + self.offset;
+        let control2 = Point::new(x2 as f64, y2 as f64) + self.offset;
+        let to = Point::new(x as f64, y as f64) + self.offset;
+        self.push(Segment::Cubic([self.current, control1, control2, to]), to);
+    }
+
+    fn close(&mut self) {
+        if self.current != self.start {
+            self.push(Segment::Line([self.current, self.start]), self.start);
+        }
+    }
+}
+
+/// Centre of the `stride` x `stride` block of output pixel `x`, `y`.
+fn sample_point(x: usize, y: usize, stride: usize) -> Point {
+    Point::new((x as f64 + 0.5) * stride as f64, (y as f64 + 0.5) * stride as f64)
+}
+
+/// Picks the next color of the edge coloring, avoiding the channels
+/// shared with `banned`.
+fn switch_color(color: EdgeColor, banned: EdgeColor) -> EdgeColor {
+    let combined = color & banned;
+    if combined == RED || combined == GREEN || combined == BLUE {
+        return combined ^ WHITE;
+    }
+
+    let shifted = color << 1;
+    (shifted | shifted >> 3) & WHITE
+}
+
+fn median(v: [f64; 3]) -> f64 {
+    v[0].min(v[1]).max(v[0].max(v[1]).min(v[2]))
+}
+
+/// Distance from an edge at which [`Outline::boundary_edges`] tells its
+/// sides apart, in pixels of the outline. It is larger than the error of
+/// the polygons approximating the curves.
+const SIDE_OFFSET: f64 = 0.25;
+
+fn edges_with_bounds(edges: &[Edge]) -> Vec<(&Edge, (Point, Point))> {
+    edges
+        .iter()
+        .map(|edge| (edge, edge.segment.bounds()))
+        .collect()
+}
+
+/// Signed area of `polygon`, positive when it winds around its inside
+/// with +1 as counted by [`winding`].
+fn area(polygon: &[Point]) -> f64 {
+    let twice: f64 = 
+        polygon
+            .iter()
+            .enumerate()
+            .map(|(i, a)| a.cross(polygon[(i + 1) % polygon.len()]))
+            .sum();
+
+    twice / 2.0
+}
+
+/// Distance from `point` to the box `bounds`, 0 inside it.
+fn bounds_distance((min, max): (Point, Point), point: Point) -> f64 {
+    let dx = (min.x - point.x).max(point.x - max.x).max(0.0);
+    let dy = (min.y - point.y).max(point.y - max.y).max(0.0);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Crossings of `polygons` with the horizontal line at `y`, as their x
+/// position and +1 or -1 by the direction of the crossing polygon edge.
+fn crossings(polygons: &[Vec<Point>], y: f64) -> Vec<(f64, i32)> {
+    let mut crossings = Vec::new();
+
+    for polygon in polygons {
+        for (i, a) in polygon.iter().enumerate() {
+            let b = polygon[(i + 1) % polygon.len()];
+            let direction =
+                if a.y <= y && b.y > y {
+                    1
+                } else if b.y <= y && a.y > y {
+                    -1
+                } else {
+                    continue;
+                };
+
+            crossings.push((a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y), direction));
+        }
+    }
+
+    crossings
+}
+
+/// Non-zero winding number around the point at `x` of the line given by
+/// `crossings`.
+fn winding(crossings: &[(f64, i32)], x: f64) -> i32 {
+    crossings
+        .iter()
+        .filter(|(crossing, _)| *crossing > x)
+        .map(|(_, direction)| direction)
+        .sum()
+}
+
+/// Whether two neighbour pixels `a` and `b` interpolate to a false edge
+/// between them, in which case `a` is flagged if it is farther from the
+/// shape than `b`.
+fn is_clash(a: [f64; 3], b: [f64; 3], threshold: f64) -> bool {
+    let inside = |p: [f64; 3]| p.iter().filter(|d| **d > 0.0).count();
+    let (a_inside, b_inside) = (inside(a), inside(b));
+
+    // Only pixels on the same side where one channel differs, not all of
+    // them at once.
+    if (a_inside >= 2) != (b_inside >= 2)
+        || a_inside == 0 || a_inside == 3 || b_inside == 0 || b_inside == 3
+    {
+        return false;
+    }
+
+    let changed: Vec<usize> =
+        (0..3)
+            .filter(|c| (a[*c] > 0.0) != (b[*c] > 0.0))
+            .collect();
+
+    if changed.len() != 2 {
+        return false;
+    }
+
+    let other = 3 - changed[0] - changed[1];
+
+    (a[changed[0]] - b[changed[0]]).abs() >= threshold
+        && (a[changed[1]] - b[changed[1]]).abs() >= threshold
+        && a[other].abs() >= b[other].abs()
+}
+
+/// Replaces the channels of pixels which would render artifacts between
+/// them and a neighbour by their median.
+fn correct_clashes(distances: &mut [[f64; 3]], width: usize, height: usize, threshold: f64) {
+    let mut clashes = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = distances[y * width + x];
+            let clash = |nx: usize, ny: usize| is_clash(pixel, distances[ny * width + nx], threshold);
+
+            if (x > 0 && clash(x - 1, y))
+                || (x + 1 < width && clash(x + 1, y))
+                || (y > 0 && clash(x, y - 1))
+                || (y + 1 < height && clash(x, y + 1))
+            {
+                clashes.push(y * width + x);
+            }
+        }
+    }
+
+    for i in clashes {
+        distances[i] = [median(distances[i]); 3];
+    }
+}
+
+/// Normalizes a distance in pixels of the outline like the single-channel
+/// SDF, positive distances are inside.
+fn normalize_distance(distance: f64, search_radius: usize) -> f32 {
+    (distance / search_radius as f64).clamp(-1.0, 1.0) as f32
+}
+
+#[test]
+fn test_generate_msdf() {
+    // A square with a triangular notch, both running clockwise.
+    let mut outline = Outline::new(Point::new(8.0, 8.0));
+    outline.move_to(0.0, 0.0);
+    outline.line_to(32.0, 0.0);
+    outline.line_to(32.0, 32.0);
+    outline.line_to(16.0, 16.0);
+    outline.line_to(0.0, 32.0);
+    outline.close();
+    outline.color_edges(3.0);
+
+    let (stride, search_radius) = (2, 8);
+    let mut msdf = MonoImage::with_channels(24, 24, 4);
+    outline.generate_msdf(&mut msdf, stride, search_radius);
+
+    for y in 0..msdf.height {
+        for x in 0..msdf.width {
+            let (px, py) = ((x * stride + 1) as f64 - 8.0, (y * stride + 1) as f64 - 8.0);
+            let inside =
+                px > 0.0 && px < 32.0 && py > 0.0 && py < 32.0
+                    && py < 16.0 + (px - 16.0).abs();
+
+            let offset = (y * msdf.width + x) * 4;
+            let pixel = &msdf.pixels[offset .. offset + 4];
+            let median =
+                pixel[0].min(pixel[1]).max(pixel[0].max(pixel[1]).min(pixel[2]));
+
+            assert_eq!(median > 0.0, inside, "at {}, {}", px, py);
+            assert_eq!(pixel[3] > 0.0, inside, "at {}, {}", px, py);
+        }
+    }
+}
+
+#[test]
+fn test_generate_sdf() {
+    // Two overlapping squares, running the opposite way of the first test.
+    let squares = |offset: Point| {
+        let mut outline = Outline::new(offset);
+        outline.move_to(0.0, 0.0);
+        outline.line_to(0.0, 20.0);
+        outline.line_to(20.0, 20.0);
+        outline.line_to(20.0, 0.0);
+        outline.close();
+        outline.move_to(10.0, 10.0);
+        outline.line_to(10.0, 30.0);
+        outline.line_to(30.0, 30.0);
+        outline.line_to(30.0, 10.0);
+        outline.close();
+        outline
+    };
+
+    // In the overlap the nearest points of the union are its inner corners
+    // at (20, 10) and (10, 20), not the edges of the squares 5 away.
+    let mut sdf = MonoImage::new(1, 1);
+    squares(Point::new(-14.5, -14.5)).generate_sdf(&mut sdf, 1, 64);
+    assert!((sdf.pixels[0] - normalize_distance(50f64.sqrt(), 64)).abs() < 1e-6);
+
+    let mut outline = squares(Point::new(-14.5, -14.5));
+    outline.color_edges(3.0);
+    let mut mtsdf = MonoImage::with_channels(1, 1, 4);
+    outline.generate_msdf(&mut mtsdf, 1, 64);
+    assert!((mtsdf.pixels[3] - normalize_distance(50f64.sqrt(), 64)).abs() < 1e-6);
+
+    let search_radius = 8;
+    let mut sdf = MonoImage::new(38, 38);
+    squares(Point::new(4.0, 4.0)).generate_sdf(&mut sdf, 1, search_radius);
+
+    // The edges of the squares inside of the other square are not part of
+    // the union, so the distance is to the outline of the union.
+    let union = [
+        (0.0, 0.0), (20.0, 0.0), (20.0, 10.0), (30.0, 10.0),
+        (30.0, 30.0), (10.0, 30.0), (10.0, 20.0), (0.0, 20.0)
+    ];
+
+    let union_distance = |px: f64, py: f64| {
+        (0..union.len())
+            .map(|i| {
+                let (a, b) = (union[i], union[(i + 1) % union.len()]);
+                let (abx, aby) = (b.0 - a.0, b.1 - a.1);
+                let t = (((px - a.0) * abx + (py - a.1) * aby) / (abx * abx + aby * aby)).clamp(0.0, 1.0);
+                (px - a.0 - abx * t).hypot(py - a.1 - aby * t)
+            })
+            .fold(f64::INFINITY, f64::min)
+    };
+
+    let inside = |px: f64, py: f64| 
+        (px > 0.0 && px < 20.0 && py > 0.0 && py < 20.0) || 
+        (px > 10.0 && px < 30.0 && py > 10.0 && py < 30.0);
+
+    for y in 0..sdf.height {
+        for x in 0..sdf.width {
+            let (px, py) = (x as f64 + 0.5 - 4.0, y as f64 + 0.5 - 4.0);
+            let distance = union_distance(px, py);
+            let expected = 
+                normalize_distance(if inside(px, py) { distance } else { -distance }, search_radius);
+
+            let pixel = sdf.pixels[y * sdf.width + x];
+            assert!((pixel - expected).abs() < 1e-6, "at {}, {}: {} != {}", px, py, pixel, expected);
+        }
+    }
+}
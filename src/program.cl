@@ -52,9 +52,12 @@ __kernel void edge_detect(
     }
 }
 
+// The SDF kernels write the squared distance d to the nearest edge pixel
+// inside the shape and ~d outside. The host normalizes them like the CPU
+// kernels, so every backend gives the same bits.
 __kernel void sdf_generate(
     __global const uchar *edge,
-    __global uchar *sdf,
+    __global int *sdf,
     int edge_width,
     int edge_height,
     int sdf_width,
@@ -71,7 +74,7 @@ __kernel void sdf_generate(
 
     bool is_inner = edge[get_offset(edge_pos, edge_size)] > 96;
 
-    int min_distance = is_inner ? 127 : -127;
+    int squared_distance = search_radius * search_radius;
 
     for(int distance = 1; distance <= search_radius; ++ distance)
     {
@@ -84,14 +87,171 @@ __kernel void sdf_generate(
 
             if(edge[top] > 192 || edge[bottom] > 192 || edge[left] > 192 || edge[right] > 192)
             {
-                float min_distancef = length((float2)(t, distance)) / (float)search_radius;
-                min_distancef = clamp(min_distancef, 0.0f, 1.0f);
-                min_distance = (int)(min_distancef * 127) * (is_inner ? 1 : -1);
+                squared_distance = t * t + distance * distance;
                 goto BREAK;
             }
         }
     }
 
 BREAK:
-    sdf[sdf_offset] = min_distance + 127;
+    sdf[sdf_offset] = is_inner ? squared_distance : ~squared_distance;
+}
+
+__kernel void jfa_seed(
+    __global const uchar *edge,
+    __global int2 *seeds,
+    int width,
+    int height)
+{
+    const int2 pos = (int2)(get_global_id(0), get_global_id(1));
+    const int offset = get_offset(pos, (int2)(width, height));
+
+    seeds[offset] = edge[offset] > 192 ? pos : (int2)(-1, -1);
+}
+
+__kernel void jfa_step(
+    __global const int2 *src,
+    __global int2 *dst,
+    int width,
+    int height,
+    int step)
+{
+    const int2 pos = (int2)(get_global_id(0), get_global_id(1));
+    const int2 size = (int2)(width, height);
+
+    int2 nearest = src[get_offset(pos, size)];
+    long nearest_distance = LONG_MAX;
+
+    if(nearest.x >= 0)
+    {
+        const long2 d = convert_long2(nearest - pos);
+        nearest_distance = d.x * d.x + d.y * d.y;
+    }
+
+    for(int y = -1; y <= 1; ++y)
+    {
+        for(int x = -1; x <= 1; ++x)
+        {
+            const int2 seed = src[get_offset(pos + (int2)(x, y) * step, size)];
+            if(seed.x >= 0)
+            {
+                const long2 d = convert_long2(seed - pos);
+                const long distance = d.x * d.x + d.y * d.y;
+                if(distance < nearest_distance)
+                {
+                    nearest = seed;
+                    nearest_distance = distance;
+                }
+            }
+        }
+    }
+
+    dst[get_offset(pos, size)] = nearest;
+}
+
+__kernel void jfa_sdf_generate(
+    __global const uchar *edge,
+    __global const int2 *seeds,
+    __global int *sdf,
+    int edge_width,
+    int edge_height,
+    int sdf_width,
+    int sdf_height,
+    int stride,
+    int search_radius)
+{
+    const int2 sdf_pos = (int2)(get_global_id(0), get_global_id(1));
+    const int2 sdf_size = (int2)(sdf_width, sdf_height);
+    const int sdf_offset = get_offset(sdf_pos, sdf_size);
+    
+    const int2 edge_pos = sdf_pos * stride + (int2)(stride / 2, stride / 2);
+    const int2 edge_size = (int2)(edge_width, edge_height);
+    const int edge_offset = get_offset(edge_pos, edge_size);
+
+    bool is_inner = edge[edge_offset] > 96;
+    const int2 seed = seeds[edge_offset];
+
+    long squared_distance = (long)search_radius * search_radius;
+    if(seed.x >= 0)
+    {
+        const int2 clamped_pos = clamp(edge_pos, (int2)(0, 0), edge_size - 1);
+        const long2 d = convert_long2(seed - clamped_pos);
+        squared_distance = min(squared_distance, d.x * d.x + d.y * d.y);
+    }
+
+    sdf[sdf_offset] = is_inner ? (int)squared_distance : ~(int)squared_distance;
+}
+
+// Distance in pixels with 16 fractional bits of a signed squared distance
+// written by the SDF kernels, clamped to the search radius. The square root
+// is taken on integers, so the host computes the same bits.
+long fixed_distance(int distance, int search_radius)
+{
+    const bool is_inner = distance >= 0;
+    const ulong squared_distance = 
+        min(is_inner ? distance : ~distance, search_radius * search_radius);
+
+    ulong x = squared_distance << 32;
+    ulong root = 0;
+    ulong bit = 1UL << 62;
+
+    while(bit > x)
+        bit >>= 2;
+
+    while(bit != 0)
+    {
+        if(x >= root + bit)
+        {
+            x -= root + bit;
+            root = (root >> 1) + bit;
+        }
+        else
+        {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    return is_inner ? (long)root : -(long)root;
+}
+
+// Reduces every stride x stride block of the signed squared distances to
+// the sum of their fixed point distances, or to the one nearest to the
+// edge if min_abs is set. The host divides them by the search radius.
+__kernel void sdf_downsample(
+    __global const int *src,
+    __global long *dst,
+    int src_width,
+    int src_height,
+    int dst_width,
+    int dst_height,
+    int stride,
+    int search_radius,
+    int min_abs)
+{
+    const int2 dst_pos = (int2)(get_global_id(0), get_global_id(1));
+    const int2 dst_size = (int2)(dst_width, dst_height);
+    const int dst_offset = get_offset(dst_pos, dst_size);
+
+    const int2 src_size = (int2)(src_width, src_height);
+
+    long sum = 0;
+    long nearest = LONG_MAX;
+
+    for(int y = 0; y < stride; ++y)
+    {
+        for(int x = 0; x < stride; ++x)
+        {
+            const long distance = 
+                fixed_distance(
+                    src[get_offset(dst_pos * stride + (int2)(x, y), src_size)], 
+                    search_radius);
+
+            sum += distance;
+            if(abs(distance) < abs(nearest))
+                nearest = distance;
+        }
+    }
+
+    dst[dst_offset] = min_abs ? nearest : sum;
 }
@@ -1,5 +1,7 @@
+use std::ops::Range;
 
-fn get_offset(
+
+pub(crate) fn get_offset(
     pos_x: i32, 
     pos_y: i32, 
     size_w: usize, 
@@ -11,6 +13,37 @@ fn get_offset(
     (pos_y * (size_w as i32) + pos_x) as usize
 }
 
+/// Splits `dst` into bands of whole rows of `row_len` elements and runs
+/// `kernel(rows, band)` for each of them on `threads` threads, where `rows`
+/// are the indices of the rows in the band.
+pub(crate) fn parallel_rows<T: Send>(
+    dst: &mut [T], 
+    row_len: usize, 
+    threads: usize, 
+    kernel: impl Fn(Range<usize>, &mut [T]) + Sync)
+{
+    if row_len == 0 {
+        return;
+    }
+
+    let rows = dst.len() / row_len;
+    let band_rows = rows.div_ceil(threads.max(1)).max(1);
+
+    if band_rows >= rows {
+        kernel(0 .. rows, dst);
+        return;
+    }
+
+    std::thread::scope(|scope| {
+        for (i, band) in dst.chunks_mut(band_rows * row_len).enumerate() {
+            let kernel = &kernel;
+            let first_row = i * band_rows;
+            scope.spawn(move || kernel(first_row .. first_row + band.len() / row_len, band));
+        }
+    });
+}
+
+/// Takes the first channel of every `stride` bytes of `src`.
 pub fn rgba_to_grayscale(
     src: &[u8],
     dst: &mut [u8],
@@ -21,130 +54,985 @@ pub fn rgba_to_grayscale(
     }
 }
 
+/// CPU version of the `edge_detect` kernel in `program.cl`, splitting the
+/// rows between `threads` threads.
 pub fn edge_detect(
     src: &[u8],
     dst: &mut [u8],
     width: usize,
-    height: usize) 
+    height: usize,
+    threads: usize) 
 {
-    let size_x = width;
-    let size_y = height;
-
-    for pos_y in 0..height {
-        for pos_x in 0..width {
-            let pos_x = pos_x as i32;
-            let pos_y = pos_y as i32;
-            let offset = 
-                get_offset(pos_x, pos_y, size_x, size_y);
-
-            if src[offset] >= 128 {
-                let mut is_edge = false;
-                
-                for pos1_y in pos_y - 1 ..= pos_y + 1 {
-                    for pos1_x in pos_x - 1 ..= pos_x + 1 {
-                        if pos1_x != pos_x || pos1_y != pos_y {
-                            let offset =
-                                get_offset(pos1_x, pos1_y, size_x, size_y);
-                            if src[offset] < 128 {
-                                is_edge = true;
-                                break;
-                            }
+    parallel_rows(&mut dst[.. width * height], width, threads, |rows, dst| {
+        for pos_y in rows.clone() {
+            for pos_x in 0..width {
+                dst[(pos_y - rows.start) * width + pos_x] = 
+                    edge_pixel(src, width, height, pos_x as i32, pos_y as i32);
+            }
+        }
+    });
+}
+
+/// The pixel at `(pos_x, pos_y)` of the edge image of `src`, see
+/// [`edge_detect`].
+pub(crate) fn edge_pixel(
+    src: &[u8], 
+    width: usize, 
+    height: usize, 
+    pos_x: i32, 
+    pos_y: i32) 
+    -> u8
+{
+    if src[get_offset(pos_x, pos_y, width, height)] < 128 {
+        return 0;
+    }
+
+    for pos1_y in pos_y - 1 ..= pos_y + 1 {
+        for pos1_x in pos_x - 1 ..= pos_x + 1 {
+            if (pos1_x != pos_x || pos1_y != pos_y) 
+                && src[get_offset(pos1_x, pos1_y, width, height)] < 128 
+            {
+                return 255;
+            }
+        }
+    }
+
+    127
+}
+
+/// CPU version of the `sdf_generate` kernel in `program.cl`.
+///
+/// Writes the distance to the nearest edge pixel as a [`DistanceSample`],
+/// for `f32` divided by `search_radius`, clamped to `-1.0..=1.0` and
+/// positive inside the shape.
+#[allow(clippy::too_many_arguments)]
+pub fn sdf_generate<T: DistanceSample>(
+    edge: &[u8],
+    sdf: &mut [T],
+    edge_width: usize,
+    edge_height: usize,
+    sdf_width: usize,
+    sdf_height: usize,
+    stride: usize,
+    search_radius: usize,
+    threads: usize) 
+{
+    parallel_rows(&mut sdf[.. sdf_width * sdf_height], sdf_width, threads, |rows, sdf| {
+        for sdf_pos_y in rows.clone() {
+            for sdf_pos_x in 0..sdf_width {
+                let edge_pos_x = (sdf_pos_x * stride + stride / 2) as i32;
+                let edge_pos_y = (sdf_pos_y * stride + stride / 2) as i32;
+
+                sdf[(sdf_pos_y - rows.start) * sdf_width + sdf_pos_x] = 
+                    ring_search(
+                        edge[get_offset(edge_pos_x, edge_pos_y, edge_width, edge_height)] > 96,
+                        search_radius, 
+                        |distance| 
+                            ring_edge(
+                                edge, 
+                                edge_width, 
+                                edge_height, 
+                                edge_pos_x, 
+                                edge_pos_y, 
+                                distance));
+            }
+        }
+    });
+}
+
+/// Scans the rings around a pixel out to `search_radius`, where
+/// `ring_edge(distance)` finds an edge pixel on the ring like [`ring_edge`],
+/// and returns the signed distance of the first one found.
+pub(crate) fn ring_search<T: DistanceSample>(
+    is_inner: bool, 
+    search_radius: usize, 
+    mut ring_edge: impl FnMut(i32) -> Option<i32>) 
+    -> T
+{
+    let squared_distance =
+        (1..=(search_radius as i32))
+            .find_map(|distance| ring_edge(distance).map(|t| t * t + distance * distance))
+            .unwrap_or((search_radius * search_radius) as i32);
+
+    T::from_squared(is_inner, squared_distance, search_radius)
+}
+
+/// Normalizes the squared distance in pixels to the nearest edge pixel to
+/// `search_radius`, negative outside the shape.
+///
+/// Every backend measures squared distances as integers and normalizes
+/// them here, so they give the same bits for the same edge image.
+pub fn normalized_distance(is_inner: bool, squared_distance: i32, search_radius: usize) -> f32 {
+    let distance = 
+        ((squared_distance as f32).sqrt() / search_radius as f32).clamp(0.0, 1.0);
+
+    if is_inner { distance } else { -distance }
+}
+
+/// A sample written by the SDF generators from the squared distance in
+/// pixels to the nearest edge pixel.
+pub trait DistanceSample: Copy + Send {
+    fn from_squared(is_inner: bool, squared_distance: i32, search_radius: usize) -> Self;
+}
+
+/// The normalized distance of [`normalized_distance`].
+impl DistanceSample for f32 {
+    fn from_squared(is_inner: bool, squared_distance: i32, search_radius: usize) -> Self {
+        normalized_distance(is_inner, squared_distance, search_radius)
+    }
+}
+
+/// The signed squared distance of the OpenCL kernels, `d` inside the shape
+/// and `!d` outside, which filters reduce without rounding.
+impl DistanceSample for i32 {
+    fn from_squared(is_inner: bool, squared_distance: i32, _: usize) -> Self {
+        if is_inner { squared_distance } else { !squared_distance }
+    }
+}
+
+/// Normalizes the signed squared distances written by the OpenCL kernels,
+/// which store the squared distance `d` inside the shape and `!d` outside.
+pub fn decode_distances(src: &[i32], dst: &mut [f32], search_radius: usize) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = 
+            if *s >= 0 { 
+                normalized_distance(true, *s, search_radius) 
+            } else { 
+                normalized_distance(false, !*s, search_radius) 
+            };
+    }
+}
+
+/// Distance in pixels with 16 fractional bits of a signed squared distance
+/// like the ones of [`decode_distances`], clamped to `search_radius`.
+///
+/// Same as `fixed_distance` in `program.cl`, the square root is taken on
+/// integers so every backend gets the same bits.
+fn fixed_distance(distance: i32, search_radius: usize) -> i64 {
+    let is_inner = distance >= 0;
+    let squared_distance = 
+        (if is_inner { distance } else { !distance }).min((search_radius * search_radius) as i32);
+
+    let mut x = (squared_distance as u64) << 32;
+    let mut root = 0;
+    let mut bit = 1u64 << 62;
+
+    while bit > x {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if x >= root + bit {
+            x -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    if is_inner { root as i64 } else { -(root as i64) }
+}
+
+/// CPU version of the `sdf_downsample` kernel in `program.cl`.
+///
+/// Reduces every `stride` x `stride` block of the full resolution signed
+/// squared distances `src` to the sum of their [`fixed_distance`]s, or to
+/// the one nearest to the edge if `min_abs` is set. The sums are normalized
+/// by [`decode_downsampled`].
+#[allow(clippy::too_many_arguments)]
+pub fn sdf_downsample_distances(
+    src: &[i32],
+    dst: &mut [i64],
+    src_width: usize,
+    src_height: usize,
+    dst_width: usize,
+    dst_height: usize,
+    stride: usize,
+    search_radius: usize,
+    min_abs: bool,
+    threads: usize)
+{
+    parallel_rows(&mut dst[.. dst_width * dst_height], dst_width, threads, |rows, dst| {
+        for dst_pos_y in rows.clone() {
+            for dst_pos_x in 0..dst_width {
+                let mut sum = 0;
+                let mut nearest = i64::MAX;
+
+                for y in 0..stride {
+                    for x in 0..stride {
+                        let src_offset = 
+                            get_offset(
+                                (dst_pos_x * stride + x) as i32, 
+                                (dst_pos_y * stride + y) as i32, 
+                                src_width, 
+                                src_height);
+
+                        let distance = fixed_distance(src[src_offset], search_radius);
+                        sum += distance;
+
+                        if distance.abs() < nearest.abs() {
+                            nearest = distance;
                         }
                     }
                 }
 
-                dst[offset] = if is_edge { 255 } else { 127 };
+                dst[(dst_pos_y - rows.start) * dst_width + dst_pos_x] = 
+                    if min_abs { nearest } else { sum };
+            }
+        }
+    });
+}
+
+/// Normalizes the sums of `samples` fixed point distances written by
+/// [`sdf_downsample_distances`] to `search_radius`.
+pub fn decode_downsampled(src: &[i64], dst: &mut [f32], samples: usize, search_radius: usize) {
+    let scale = (samples * search_radius) as f64 * 65536.0;
+
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = (*s as f64 / scale) as f32;
+    }
+}
+
+/// Squared distance between two pixels, wide enough for any image.
+fn squared_distance(x0: i32, y0: i32, x1: i32, y1: i32) -> i64 {
+    let (dx, dy) = ((x0 - x1) as i64, (y0 - y1) as i64);
+    dx * dx + dy * dy
+}
+
+/// The first offset `t` from `-distance` to `distance` along the sides of
+/// the square ring `distance` pixels around `(pos_x, pos_y)` where the top,
+/// bottom, left or right side has an edge pixel (`> 192`).
+pub(crate) fn ring_edge(
+    edge: &[u8],
+    width: usize,
+    height: usize,
+    pos_x: i32,
+    pos_y: i32,
+    distance: i32)
+    -> Option<i32>
+{
+    (-distance..=distance).find(|&t| {
+        let top = get_offset(pos_x + t, pos_y - distance, width, height);
+        let bottom = get_offset(pos_x + t, pos_y + distance, width, height);
+        let left = get_offset(pos_x - distance, pos_y + t, width, height);
+        let right = get_offset(pos_x + distance, pos_y + t, width, height);
+
+        edge[top] > 192 || edge[bottom] > 192 || edge[left] > 192 || edge[right] > 192
+    })
+}
+
+/// Marks a pixel without any edge pixel in its column.
+const NO_SITE: i32 = i32::MIN;
+
+/// Nearest edge pixel (`edge > 192`) in the same column for every pixel,
+/// stored as its row or [`NO_SITE`].
+fn nearest_column_sites(edge: &[u8], width: usize, height: usize) -> Vec<i32> {
+    let mut sites = vec![NO_SITE; width * height];
+
+    for x in 0..width {
+        let mut last = NO_SITE;
+        for y in 0..height {
+            if edge[y * width + x] > 192 {
+                last = y as i32;
+            }
+            sites[y * width + x] = last;
+        }
+
+        let mut last = NO_SITE;
+        for y in (0..height).rev() {
+            if edge[y * width + x] > 192 {
+                last = y as i32;
+            }
+
+            let above = sites[y * width + x];
+            let y = y as i32;
+            if last != NO_SITE && (above == NO_SITE || last - y < y - above) {
+                sites[y as usize * width + x] = last;
+            }
+        }
+    }
+
+    sites
+}
+
+/// Nearest edge pixel of every pixel in row `y` as `(x, y)`, given the
+/// nearest sites of every column.
+///
+/// This is the one dimensional transform of Felzenszwalb and Huttenlocher,
+/// finding the lower envelope of the parabolas rooted at the column sites.
+/// `envelope` and `bounds` are scratch buffers.
+///
+/// The parabolas are intersected in `i64` and `f64`, since `q * q` has no
+/// exact `f32` past 4096 pixels and overflows `i32` past 46340.
+fn nearest_row_sites(
+    column_sites: &[i32],
+    width: usize,
+    y: usize,
+    envelope: &mut Vec<i32>,
+    bounds: &mut Vec<f64>,
+    nearest: &mut [(i32, i32)])
+{
+    let row = &column_sites[y * width .. (y + 1) * width];
+    let f = |q: i32| {
+        let (q, dy) = (q as i64, y as i64 - row[q as usize] as i64);
+        dy * dy + q * q
+    };
+
+    envelope.clear();
+    bounds.clear();
+
+    for q in 0..width as i32 {
+        if row[q as usize] == NO_SITE {
+            continue;
+        }
+
+        loop {
+            match envelope.last() {
+                None => {
+                    envelope.push(q);
+                    bounds.push(f64::NEG_INFINITY);
+                    break;
+                },
+                Some(&v) => {
+                    let s = (f(q) - f(v)) as f64 / (2 * (q - v)) as f64;
+
+                    if s <= *bounds.last().unwrap() {
+                        envelope.pop();
+                        bounds.pop();
+                    } else {
+                        envelope.push(q);
+                        bounds.push(s);
+                        break;
+                    }
+                }
             }
-            else {
-                dst[offset] = 0;
+        }
+    }
+
+    if envelope.is_empty() {
+        for i in nearest.iter_mut() {
+            *i = (NO_SITE, NO_SITE);
+        }
+        return;
+    }
+
+    let mut k = 0;
+    for (x, i) in nearest.iter_mut().enumerate() {
+        while k + 1 < envelope.len() && bounds[k + 1] < x as f64 {
+            k += 1;
+        }
+
+        let q = envelope[k];
+        *i = (q, row[q as usize]);
+    }
+}
+
+/// Same as [`sdf_generate`], but finds the nearest edge pixel with an exact
+/// Euclidean distance transform in time linear to the edge image size,
+/// independent of `search_radius`.
+#[allow(clippy::too_many_arguments)]
+pub fn sdf_generate_exact<T: DistanceSample>(
+    edge: &[u8],
+    sdf: &mut [T],
+    edge_width: usize,
+    edge_height: usize,
+    sdf_width: usize,
+    sdf_height: usize,
+    stride: usize,
+    search_radius: usize,
+    threads: usize) 
+{
+    let column_sites = nearest_column_sites(edge, edge_width, edge_height);
+    let max_distance = (search_radius * search_radius) as i64;
+
+    parallel_rows(&mut sdf[.. sdf_width * sdf_height], sdf_width, threads, |rows, sdf| {
+        let mut envelope = Vec::with_capacity(edge_width);
+        let mut bounds = Vec::with_capacity(edge_width);
+        let mut nearest = vec![(NO_SITE, NO_SITE); edge_width];
+
+        for sdf_pos_y in rows.clone() {
+            let edge_pos_y = 
+                (sdf_pos_y * stride + stride / 2).min(edge_height - 1);
+
+            nearest_row_sites(
+                &column_sites, 
+                edge_width, 
+                edge_pos_y, 
+                &mut envelope, 
+                &mut bounds, 
+                &mut nearest);
+
+            for sdf_pos_x in 0..sdf_width {
+                let edge_pos_x = 
+                    (sdf_pos_x * stride + stride / 2).min(edge_width - 1);
+
+                let is_inner = 
+                    edge[edge_pos_y * edge_width + edge_pos_x] > 96;
+
+                let (site_x, site_y) = nearest[edge_pos_x];
+
+                let squared_distance = 
+                    if site_x == NO_SITE {
+                        max_distance
+                    } else {
+                        squared_distance(site_x, site_y, edge_pos_x as i32, edge_pos_y as i32)
+                            .min(max_distance)
+                    };
+
+                sdf[(sdf_pos_y - rows.start) * sdf_width + sdf_pos_x] = 
+                    T::from_squared(is_inner, squared_distance as i32, search_radius);
             }
         }
+    });
+}
+
+/// Steps of the jump flooding passes, halving from the largest power of two
+/// not above `search_radius`, then one more pass of step 1 to fix most of
+/// the errors.
+pub(crate) fn jump_flood_steps(search_radius: usize) -> Vec<usize> {
+    let mut steps = Vec::new();
+    let mut step = 1;
+    while step * 2 <= search_radius {
+        step *= 2;
     }
+    while step >= 1 {
+        steps.push(step);
+        step /= 2;
+    }
+    steps.push(1);
+    steps
 }
 
-pub fn sdf_generate(
+/// CPU version of the jump flooding kernels in `program.cl`, which finds
+/// the nearest edge pixel of every pixel in the passes of
+/// [`jump_flood_steps`] and gives the same distances as the OpenCL devices,
+/// close to the exact transform of [`sdf_generate_exact`].
+#[allow(clippy::too_many_arguments)]
+pub fn sdf_generate_jump_flood<T: DistanceSample>(
     edge: &[u8],
-    sdf: &mut [u8],
+    sdf: &mut [T],
     edge_width: usize,
     edge_height: usize,
     sdf_width: usize,
     sdf_height: usize,
     stride: usize,
-    search_radius: usize) 
+    search_radius: usize,
+    threads: usize) 
+{
+    let mut seeds: Vec<(i32, i32)> =
+        (0..edge_width * edge_height)
+            .map(|i| 
+                if edge[i] > 192 { 
+                    ((i % edge_width) as i32, (i / edge_width) as i32) 
+                } else { 
+                    (-1, -1) 
+                })
+            .collect();
+
+    let mut next = seeds.clone();
+
+    for step in jump_flood_steps(search_radius) {
+        let src = &seeds;
+        let step = step as i32;
+
+        parallel_rows(&mut next, edge_width, threads, |rows, dst| {
+            for y in rows.clone() {
+                for x in 0..edge_width {
+                    let (pos_x, pos_y) = (x as i32, y as i32);
+                    let mut nearest = src[y * edge_width + x];
+                    let mut nearest_distance = 
+                        if nearest.0 >= 0 { 
+                            squared_distance(nearest.0, nearest.1, pos_x, pos_y) 
+                        } else { 
+                            i64::MAX 
+                        };
+
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let seed = 
+                                src[get_offset(
+                                    pos_x + dx * step, 
+                                    pos_y + dy * step, 
+                                    edge_width, 
+                                    edge_height)];
+
+                            if seed.0 >= 0 {
+                                let distance = squared_distance(seed.0, seed.1, pos_x, pos_y);
+                                if distance < nearest_distance {
+                                    nearest = seed;
+                                    nearest_distance = distance;
+                                }
+                            }
+                        }
+                    }
+
+                    dst[(y - rows.start) * edge_width + x] = nearest;
+                }
+            }
+        });
+
+        std::mem::swap(&mut seeds, &mut next);
+    }
+
+    let max_distance = (search_radius * search_radius) as i64;
+
+    parallel_rows(&mut sdf[.. sdf_width * sdf_height], sdf_width, threads, |rows, sdf| {
+        for sdf_pos_y in rows.clone() {
+            for sdf_pos_x in 0..sdf_width {
+                let edge_pos_x = (sdf_pos_x * stride + stride / 2).min(edge_width - 1);
+                let edge_pos_y = (sdf_pos_y * stride + stride / 2).min(edge_height - 1);
+                let edge_offset = edge_pos_y * edge_width + edge_pos_x;
+
+                let (seed_x, seed_y) = seeds[edge_offset];
+                let squared_distance =
+                    if seed_x >= 0 {
+                        squared_distance(seed_x, seed_y, edge_pos_x as i32, edge_pos_y as i32)
+                            .min(max_distance)
+                    } else {
+                        max_distance
+                    };
+
+                sdf[(sdf_pos_y - rows.start) * sdf_width + sdf_pos_x] = 
+                    T::from_squared(edge[edge_offset] > 96, squared_distance as i32, search_radius);
+            }
+        }
+    });
+}
+
+/// Distance from the centre of a pixel with coverage `a` to the edge
+/// crossing it, along the gradient `(gx, gy)`. Positive when the centre is
+/// outside the shape.
+///
+/// From "Anti-aliased Euclidean distance transform" by Gustavson and
+/// Strand, which models the edge as a straight line through the pixel.
+fn edge_distance(gx: f32, gy: f32, a: f32) -> f32 {
+    if gx == 0.0 || gy == 0.0 {
+        return 0.5 - a;
+    }
+
+    let length = (gx * gx + gy * gy).sqrt();
+    let (gx, gy) = ((gx / length).abs(), (gy / length).abs());
+    let (gx, gy) = if gx < gy { (gy, gx) } else { (gx, gy) };
+
+    let a1 = 0.5 * gy / gx;
+    if a < a1 {
+        0.5 * (gx + gy) - (2.0 * gx * gy * a).sqrt()
+    } else if a < 1.0 - a1 {
+        (0.5 - a) * gx
+    } else {
+        -0.5 * (gx + gy) + (2.0 * gx * gy * (1.0 - a)).sqrt()
+    }
+}
+
+/// Estimates the sub-pixel edge position of every pixel the edge of the
+/// shape passes through, from the coverage of the pixel and the gradient
+/// of its neighbours.
+///
+/// Returns an edge image (255 at edge pixels, like [`edge_detect`]) and the
+/// edge position of every edge pixel as an offset from its centre.
+fn anti_aliased_edges(
+    coverage: &[u8],
+    width: usize,
+    height: usize)
+    -> (Vec<u8>, Vec<(f32, f32)>)
+{
+    let mut edge = vec![0; width * height];
+    let mut offsets = vec![(0.0, 0.0); width * height];
+
+    let a = |x: i32, y: i32| 
+        coverage[get_offset(x, y, width, height)] as f32 / 255.0;
+
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let center = a(x, y);
+
+            // Covered and empty pixels are only edges at hard steps, next
+            // to the edge the partly covered pixels place more precisely.
+            let mut is_edge = center > 0.0 && center < 1.0;
+            for ny in y - 1 ..= y + 1 {
+                for nx in x - 1 ..= x + 1 {
+                    is_edge |= (a(nx, ny) - center).abs() == 1.0;
+                }
+            }
+
+            if !is_edge {
+                continue;
+            }
+
+            let gx = 
+                -a(x - 1, y - 1) - std::f32::consts::SQRT_2 * a(x - 1, y) - a(x - 1, y + 1)
+                + a(x + 1, y - 1) + std::f32::consts::SQRT_2 * a(x + 1, y) + a(x + 1, y + 1);
+            let gy = 
+                -a(x - 1, y - 1) - std::f32::consts::SQRT_2 * a(x, y - 1) - a(x + 1, y - 1)
+                + a(x - 1, y + 1) + std::f32::consts::SQRT_2 * a(x, y + 1) + a(x + 1, y + 1);
+
+            let offset = y as usize * width + x as usize;
+            edge[offset] = 255;
+
+            let length = (gx * gx + gy * gy).sqrt();
+            if length > 0.0 {
+                let d = edge_distance(gx, gy, center);
+                offsets[offset] = (gx / length * d, gy / length * d);
+            }
+        }
+    }
+
+    (edge, offsets)
+}
+
+/// Same as [`sdf_generate_exact`], but works on the anti-aliased grayscale
+/// image `src` instead of an edge image, measuring the distance to the
+/// sub-pixel edge position estimated from the coverage of the pixels.
+///
+/// The nearest edge pixel is found by the exact transform, then the nearest
+/// sub-pixel edge of it and its neighbours is taken. Distances are measured
+/// from the exact centre of every `stride` x `stride` block, half a source
+/// pixel up and left of the pixel [`sdf_generate`] samples for even
+/// strides.
+#[allow(clippy::too_many_arguments)]
+pub fn sdf_generate_anti_aliased(
+    src: &[u8],
+    sdf: &mut [f32],
+    width: usize,
+    height: usize,
+    sdf_width: usize,
+    sdf_height: usize,
+    stride: usize,
+    search_radius: usize,
+    threads: usize)
+{
+    let (edge, offsets) = anti_aliased_edges(src, width, height);
+    let column_sites = nearest_column_sites(&edge, width, height);
+
+    // Sample at the centre of the area covered by an output pixel. Pixel
+    // centres have whole coordinates, so for even strides it lies half a
+    // pixel before the pixel `pos * stride + stride / 2` the other
+    // algorithms sample, which have to measure from whole pixels.
+    let sample_pos = |pos: usize| (pos * stride) as f32 + stride as f32 / 2.0 - 0.5;
+
+    let coverage = |x: f32, y: f32| {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let p = |x, y| src[get_offset(x, y, width, height)] as f32;
+
+        (p(x0, y0) * (1.0 - fx) + p(x0 + 1, y0) * fx) * (1.0 - fy)
+            + (p(x0, y0 + 1) * (1.0 - fx) + p(x0 + 1, y0 + 1) * fx) * fy
+    };
+
+    parallel_rows(&mut sdf[.. sdf_width * sdf_height], sdf_width, threads, |rows, sdf| {
+        let mut envelope = Vec::with_capacity(width);
+        let mut bounds = Vec::with_capacity(width);
+        let mut nearest = vec![(NO_SITE, NO_SITE); width];
+
+        for sdf_pos_y in rows.clone() {
+            let y = sample_pos(sdf_pos_y);
+            let row = (y.round() as usize).min(height - 1);
+
+            nearest_row_sites(
+                &column_sites, 
+                width, 
+                row, 
+                &mut envelope, 
+                &mut bounds, 
+                &mut nearest);
+
+            for sdf_pos_x in 0..sdf_width {
+                let x = sample_pos(sdf_pos_x);
+                let column = (x.round() as usize).min(width - 1);
+                let (site_x, site_y) = nearest[column];
+
+                let mut min_distance = f32::INFINITY;
+                if site_x != NO_SITE {
+                    for ny in site_y - 1 ..= site_y + 1 {
+                        for nx in site_x - 1 ..= site_x + 1 {
+                            if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                                continue;
+                            }
+
+                            let offset = ny as usize * width + nx as usize;
+                            if edge[offset] == 0 {
+                                continue;
+                            }
+
+                            let (ox, oy) = offsets[offset];
+                            let dx = nx as f32 + ox - x;
+                            let dy = ny as f32 + oy - y;
+                            min_distance = min_distance.min((dx * dx + dy * dy).sqrt());
+                        }
+                    }
+                }
+
+                let is_inner = coverage(x, y) >= 127.5;
+                let min_distancef = (min_distance / search_radius as f32).clamp(0.0, 1.0);
+                sdf[(sdf_pos_y - rows.start) * sdf_width + sdf_pos_x] = 
+                    if is_inner { min_distancef } else { -min_distancef };
+            }
+        }
+    });
+}
+
+/// Reduces every `stride` x `stride` block of the full resolution distances
+/// `src` to one pixel of `dst`, taking the distance nearest to the edge if
+/// `min_abs` is set and the average otherwise.
+///
+/// Used for the anti-aliased distances, which are not whole pixels. The
+/// other algorithms reduce their squared distances with
+/// [`sdf_downsample_distances`] like the OpenCL devices do.
+#[allow(clippy::too_many_arguments)]
+pub fn sdf_downsample(
+    src: &[f32],
+    dst: &mut [f32],
+    src_width: usize,
+    src_height: usize,
+    dst_width: usize,
+    dst_height: usize,
+    stride: usize,
+    min_abs: bool,
+    threads: usize)
 {
-    let sdf_size_x = sdf_width;
-    let sdf_size_y = sdf_height;
-    let edge_size_x = edge_width;
-    let edge_size_y = edge_height;
-
-    for sdf_pos_y in 0..sdf_height {
-        for sdf_pos_x in 0..sdf_width {
-            let sdf_pos_x = sdf_pos_x as i32;
-            let sdf_pos_y = sdf_pos_y as i32;
-
-            let sdf_offset = 
-                get_offset(sdf_pos_x, sdf_pos_y, sdf_size_x, sdf_size_y);
-    
-            let edge_pos_x = (sdf_pos_x * stride as i32 + stride as i32 / 2) as i32;
-            let edge_pos_y = (sdf_pos_y * stride as i32 + stride as i32 / 2) as i32;
-            let edge_offset = 
-                get_offset(
-                    edge_pos_x, 
-                    edge_pos_y, 
-                    edge_size_x, 
-                    edge_size_y);
-
-            let is_inner = 
-                edge[edge_offset] > 96;
-
-            let mut min_distance = if is_inner { 127 } else { -127 };
-
-            'outer: for distance in 1..=(search_radius as i32) {
-                for t in -distance..=distance {
-                    let top = 
-                        get_offset(
-                            edge_pos_x + t,
-                            edge_pos_y - distance,
-                            edge_size_x,
-                            edge_size_y);
-
-                    let bottom = 
-                        get_offset(
-                            edge_pos_x + t,
-                            edge_pos_y + distance,
-                            edge_size_x,
-                            edge_size_y);
-
-                    let left = 
-                        get_offset(
-                            edge_pos_x - distance,
-                            edge_pos_y + t, 
-                            edge_size_x,
-                            edge_size_y);
-
-                    let right = 
-                        get_offset(
-                            edge_pos_x + distance, 
-                            edge_pos_y + t, 
-                            edge_size_x,
-                            edge_size_y);
-
-
-                    if edge[top] > 192 || edge[bottom] > 192 || edge[left] > 192 || edge[right] > 192 {
-                        let min_distancef = 
-                            ((t * t + distance * distance) as f32).sqrt() / (search_radius as f32);
-
-                        let min_distancef = min_distancef.clamp(0.0, 1.0);
-                        min_distance = (min_distancef * 127.0) as i32 * (if is_inner { 1 } else { -1 });
-                        break 'outer;
+    parallel_rows(&mut dst[.. dst_width * dst_height], dst_width, threads, |rows, dst| {
+        for dst_pos_y in rows.clone() {
+            for dst_pos_x in 0..dst_width {
+                let mut sum = 0.0;
+                let mut nearest = f32::INFINITY;
+
+                for y in 0..stride {
+                    for x in 0..stride {
+                        let src_offset = 
+                            get_offset(
+                                (dst_pos_x * stride + x) as i32, 
+                                (dst_pos_y * stride + y) as i32, 
+                                src_width, 
+                                src_height);
+
+                        let distance = src[src_offset];
+                        sum += distance;
+
+                        if distance.abs() < nearest.abs() {
+                            nearest = distance;
+                        }
                     }
                 }
+
+                dst[(dst_pos_y - rows.start) * dst_width + dst_pos_x] = 
+                    if min_abs { nearest } else { sum / (stride * stride) as f32 };
             }
-            sdf[sdf_offset] = (min_distance + 127) as u8;
         }
+    });
+}
+
+#[test]
+fn test_sdf_generate_exact() {
+    let (width, height) = (61, 47);
+    let mut image = vec![0; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let (dx, dy) = (x as i32 - 30, y as i32 - 20);
+            let in_circle = dx * dx + dy * dy < 15 * 15;
+            let in_bar = x > 40 && y > 5 && y < 12;
+            image[y * width + x] = if in_circle || in_bar { 255 } else { 0 };
+        }
+    }
+
+    let mut edge = vec![0; width * height];
+    edge_detect(&image, &mut edge, width, height, 1);
+
+    let (search_radius, stride) = (64, 3);
+    let (sdf_width, sdf_height) = (width / stride, height / stride);
+    let mut sdf = vec![0.0; sdf_width * sdf_height];
+
+    sdf_generate_exact(
+        &edge, &mut sdf, width, height, sdf_width, sdf_height, stride, search_radius, 4);
+
+    for sdf_y in 0..sdf_height {
+        for sdf_x in 0..sdf_width {
+            let (x, y) = (sdf_x * stride + stride / 2, sdf_y * stride + stride / 2);
+
+            let nearest =
+                (0..width * height)
+                    .filter(|i| edge[*i] > 192)
+                    .map(|i| {
+                        let dx = (i % width) as f32 - x as f32;
+                        let dy = (i / width) as f32 - y as f32;
+                        (dx * dx + dy * dy).sqrt()
+                    })
+                    .fold(f32::INFINITY, f32::min);
+
+            let sign = if edge[y * width + x] > 96 { 1.0 } else { -1.0 };
+            let expected = (nearest / search_radius as f32).clamp(0.0, 1.0) * sign;
+
+            assert_eq!(sdf[sdf_y * sdf_width + sdf_x], expected, "at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_sdf_downsample() {
+    // A vertical line one pixel wide, which point sampling at stride 4
+    // never hits.
+    let (width, height, stride) = (16, 8, 4);
+    let image: Vec<u8> = 
+        (0..width * height).map(|i| if i % width == 5 { 255 } else { 0 }).collect();
+
+    let mut edge = vec![0; width * height];
+    edge_detect(&image, &mut edge, width, height, 1);
+
+    let mut full = vec![0.0; width * height];
+    sdf_generate_exact(&edge, &mut full, width, height, width, height, 1, 8, 1);
+
+    let (sdf_width, sdf_height) = (width / stride, height / stride);
+    let mut min_abs = vec![0.0; sdf_width * sdf_height];
+    let mut average = vec![0.0; sdf_width * sdf_height];
+
+    sdf_downsample(&full, &mut min_abs, width, height, sdf_width, sdf_height, stride, true, 3);
+    sdf_downsample(&full, &mut average, width, height, sdf_width, sdf_height, stride, false, 1);
+
+    for y in 0..sdf_height {
+        assert_eq!(min_abs[y * sdf_width + 1], 0.0);
+        assert_eq!(average[y * sdf_width + 1], (-0.125 * 2.0 - 0.25) / 4.0);
+        assert_eq!(min_abs[y * sdf_width + 2], -0.375);
     }
+
+    // The squared distances of the OpenCL devices reduce to the same
+    // values, with the square roots taken on integers.
+    let mut squared = vec![0; width * height];
+    sdf_generate_exact(&edge, &mut squared, width, height, width, height, 1, 8, 1);
+
+    for (min_abs_filter, expected, samples) in [(true, &min_abs, 1), (false, &average, stride * stride)] {
+        let mut reduced = vec![0; sdf_width * sdf_height];
+        let mut decoded = vec![0.0; sdf_width * sdf_height];
+
+        sdf_downsample_distances(
+            &squared, &mut reduced, width, height, sdf_width, sdf_height, stride, 8, min_abs_filter, 2);
+        decode_downsampled(&reduced, &mut decoded, samples, 8);
+        assert_eq!(&decoded, expected);
+    }
+
+    assert_eq!(fixed_distance(2, 8), 92681);
+    assert_eq!(fixed_distance(!100, 8), -8 << 16);
 }
 
+#[test]
+fn test_sdf_generate_jump_flood() {
+    let (width, height) = (61, 47);
+    let image: Vec<u8> = 
+        (0..width * height)
+            .map(|i| {
+                let (dx, dy) = ((i % width) as i32 - 30, (i / width) as i32 - 20);
+                if dx * dx + dy * dy < 15 * 15 || (i % width > 40 && i / width == 30) { 255 } else { 0 }
+            })
+            .collect();
+
+    let mut edge = vec![0; width * height];
+    edge_detect(&image, &mut edge, width, height, 1);
+
+    let (search_radius, stride) = (32, 2);
+    let (sdf_width, sdf_height) = (width / stride, height / stride);
+    let mut exact = vec![0.0; sdf_width * sdf_height];
+    let mut jump_flood = vec![0.0; sdf_width * sdf_height];
+
+    sdf_generate_exact(
+        &edge, &mut exact, width, height, sdf_width, sdf_height, stride, search_radius, 1);
+    sdf_generate_jump_flood(
+        &edge, &mut jump_flood, width, height, sdf_width, sdf_height, stride, search_radius, 3);
+
+    // Jump flooding can miss the nearest edge pixel for a slightly farther
+    // one, but never crosses the edge.
+    for (exact, jump_flood) in exact.iter().zip(&jump_flood) {
+        assert_eq!(exact.is_sign_negative(), jump_flood.is_sign_negative());
+        assert!(jump_flood.abs() >= exact.abs());
+        assert!(jump_flood.abs() - exact.abs() < 1.0 / search_radius as f32);
+    }
+}
+
+#[test]
+fn test_nearest_row_sites_wide() {
+    // Past 4096 pixels `x * x` has no exact f32, so the envelope must be
+    // intersected with wider numbers to keep the nearest site.
+    let (width, height, y) = (12000, 64, 40);
+    let mut seed = 1u32;
+    let mut random = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as i32
+    };
+
+    let column_sites: Vec<i32> = 
+        (0..width * height)
+            .map(|_| if random() % 4 == 0 { random() % height as i32 } else { NO_SITE })
+            .collect();
 
+    let row = &column_sites[y * width .. (y + 1) * width];
+    let sites: Vec<(i32, i32)> = 
+        (0..width as i32)
+            .filter(|&x| row[x as usize] != NO_SITE)
+            .map(|x| (x, row[x as usize]))
+            .collect();
 
-    
+    let mut nearest = vec![(NO_SITE, NO_SITE); width];
+    nearest_row_sites(&column_sites, width, y, &mut Vec::new(), &mut Vec::new(), &mut nearest);
+
+    for (x, &(site_x, site_y)) in nearest.iter().enumerate() {
+        let (x, y) = (x as i32, y as i32);
+        let expected = 
+            sites
+                .iter()
+                .map(|&(sx, sy)| squared_distance(sx, sy, x, y))
+                .min()
+                .unwrap();
+
+        assert_eq!(squared_distance(site_x, site_y, x, y), expected, "at {}", x);
+    }
+}
+
+#[test]
+fn test_sdf_generate_anti_aliased() {
+    // A circle anti-aliased with 16 x 16 samples per pixel, whose centres
+    // are at whole coordinates.
+    let (width, height, stride, search_radius) = (64, 64, 2, 8);
+    let (cx, cy, radius) = (31.3, 32.6, 20.3);
+
+    let image: Vec<u8> = 
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                let inside = 
+                    (0..256)
+                        .filter(|j| {
+                            let dx = x - 0.5 + ((j % 16) as f32 + 0.5) / 16.0 - cx;
+                            let dy = y - 0.5 + ((j / 16) as f32 + 0.5) / 16.0 - cy;
+                            dx * dx + dy * dy < radius * radius
+                        })
+                        .count();
+
+                (inside * 255 / 256) as u8
+            })
+            .collect();
+
+    let (sdf_width, sdf_height) = (width / stride, height / stride);
+    let mut sdf = vec![0.0; sdf_width * sdf_height];
+
+    sdf_generate_anti_aliased(
+        &image, &mut sdf, width, height, sdf_width, sdf_height, stride, search_radius, 2);
+
+    // Distances are measured from the centre of the 2 x 2 block of every
+    // output pixel, half a pixel before the pixel `sdf_generate` samples,
+    // which would be off by 0.45 pixels on average here.
+    let mut errors = Vec::new();
+    for sdf_y in 0..sdf_height {
+        for sdf_x in 0..sdf_width {
+            let (x, y) = ((sdf_x * stride) as f32 + 0.5, (sdf_y * stride) as f32 + 0.5);
+            let distance = radius - ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+
+            if distance.abs() < search_radius as f32 {
+                let error = sdf[sdf_y * sdf_width + sdf_x] * search_radius as f32 - distance;
+                assert!(error.abs() < 0.5, "{} off at {}, {}", error, x, y);
+                errors.push(error.abs());
+            }
+        }
+    }
+
+    assert!(errors.iter().sum::<f32>() / (errors.len() as f32) < 0.05);
+}
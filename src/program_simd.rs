@@ -0,0 +1,560 @@
+use std::iter;
+
+use crate::program_cpu::{self, get_offset, parallel_rows, DistanceSample};
+
+pub use arch::Simd;
+
+/// [`Simd::rgba_to_grayscale`] with the fastest instruction set of the CPU.
+pub fn rgba_to_grayscale(
+    src: &[u8],
+    dst: &mut [u8],
+    stride: usize)
+{
+    Simd::detect().rgba_to_grayscale(src, dst, stride);
+}
+
+/// [`Simd::edge_detect`] with the fastest instruction set of the CPU.
+pub fn edge_detect(
+    src: &[u8],
+    dst: &mut [u8],
+    width: usize,
+    height: usize,
+    threads: usize)
+{
+    Simd::detect().edge_detect(src, dst, width, height, threads);
+}
+
+/// [`Simd::sdf_generate`] with the fastest instruction set of the CPU.
+#[allow(clippy::too_many_arguments)]
+pub fn sdf_generate<T: DistanceSample>(
+    edge: &[u8],
+    sdf: &mut [T],
+    edge_width: usize,
+    edge_height: usize,
+    sdf_width: usize,
+    sdf_height: usize,
+    stride: usize,
+    search_radius: usize,
+    threads: usize)
+{
+    Simd::detect().sdf_generate(
+        edge, sdf, edge_width, edge_height, sdf_width, sdf_height, stride, search_radius, threads);
+}
+
+impl Simd {
+    /// Same as [`program_cpu::rgba_to_grayscale`], vectorized for 4
+    /// channels.
+    pub fn rgba_to_grayscale(
+        self,
+        src: &[u8],
+        dst: &mut [u8],
+        stride: usize)
+    {
+        let done = if stride == 4 { self.grayscale4(src, dst) } else { 0 };
+        program_cpu::rgba_to_grayscale(&src[done * stride ..], &mut dst[done ..], stride);
+    }
+
+    /// Same as [`program_cpu::edge_detect`], vectorized for all pixels
+    /// except the border of the image, where the neighbours are clamped.
+    pub fn edge_detect(
+        self,
+        src: &[u8],
+        dst: &mut [u8],
+        width: usize,
+        height: usize,
+        threads: usize)
+    {
+        parallel_rows(&mut dst[.. width * height], width, threads, |rows, dst| {
+            for (pos_y, out) in rows.zip(dst.chunks_mut(width)) {
+                let done =
+                    if pos_y > 0 && pos_y + 1 < height {
+                        let row = |y: usize| &src[y * width .. (y + 1) * width];
+                        self.edge_row(row(pos_y - 1), row(pos_y), row(pos_y + 1), out)
+                    } else {
+                        0
+                    };
+
+                for pos_x in iter::once(0).chain(1 + done .. width) {
+                    out[pos_x] =
+                        program_cpu::edge_pixel(src, width, height, pos_x as i32, pos_y as i32);
+                }
+            }
+        });
+    }
+
+    /// Same as [`program_cpu::sdf_generate`], scanning the sides of the
+    /// rings around every pixel with SIMD.
+    ///
+    /// The left and right sides are scanned in a transposed copy of `edge`,
+    /// which keeps them contiguous in memory.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sdf_generate<T: DistanceSample>(
+        self,
+        edge: &[u8],
+        sdf: &mut [T],
+        edge_width: usize,
+        edge_height: usize,
+        sdf_width: usize,
+        sdf_height: usize,
+        stride: usize,
+        search_radius: usize,
+        threads: usize)
+    {
+        let transposed = transpose(edge, edge_width, edge_height);
+
+        parallel_rows(&mut sdf[.. sdf_width * sdf_height], sdf_width, threads, |rows, sdf| {
+            for sdf_pos_y in rows.clone() {
+                for sdf_pos_x in 0..sdf_width {
+                    let edge_pos_x = (sdf_pos_x * stride + stride / 2) as i32;
+                    let edge_pos_y = (sdf_pos_y * stride + stride / 2) as i32;
+
+                    sdf[(sdf_pos_y - rows.start) * sdf_width + sdf_pos_x] =
+                        program_cpu::ring_search(
+                            edge[get_offset(edge_pos_x, edge_pos_y, edge_width, edge_height)] > 96,
+                            search_radius,
+                            |distance|
+                                ring_edge(
+                                    self,
+                                    edge,
+                                    &transposed,
+                                    edge_width,
+                                    edge_height,
+                                    edge_pos_x,
+                                    edge_pos_y,
+                                    distance));
+                }
+            }
+        });
+    }
+}
+
+fn transpose(src: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut dst = vec![0; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            dst[x * height + y] = src[y * width + x];
+        }
+    }
+
+    dst
+}
+
+/// `len` pixels of `image` starting at `(x, row)`.
+fn side(image: &[u8], row_len: usize, x: i32, row: i32, len: usize) -> &[u8] {
+    &image[row as usize * row_len + x as usize ..][.. len]
+}
+
+/// Same as [`program_cpu::ring_edge`], falling back to it for rings which
+/// leave the image.
+#[allow(clippy::too_many_arguments)]
+fn ring_edge(
+    simd: Simd,
+    edge: &[u8],
+    transposed: &[u8],
+    width: usize,
+    height: usize,
+    pos_x: i32,
+    pos_y: i32,
+    distance: i32)
+    -> Option<i32>
+{
+    if pos_x < distance
+        || pos_y < distance
+        || pos_x + distance >= width as i32
+        || pos_y + distance >= height as i32
+    {
+        return program_cpu::ring_edge(edge, width, height, pos_x, pos_y, distance);
+    }
+
+    let len = (2 * distance + 1) as usize;
+    let (left, top) = (pos_x - distance, pos_y - distance);
+    let (right, bottom) = (pos_x + distance, pos_y + distance);
+
+    let top_bottom =
+        simd.first_edge(
+            side(edge, width, left, top, len),
+            side(edge, width, left, bottom, len));
+
+    let left_right =
+        simd.first_edge(
+            side(transposed, height, top, left, len),
+            side(transposed, height, top, right, len));
+
+    top_bottom.into_iter().chain(left_right).min().map(|t| t as i32 - distance)
+}
+
+/// SSE2, which every x86-64 CPU has, and AVX2 if the CPU supports it.
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use std::arch::x86_64::*;
+
+    /// An instruction set for the vectorized kernels.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Simd {
+        avx2: bool
+    }
+
+    impl Simd {
+        /// The fastest instruction set of the CPU.
+        pub fn detect() -> Self {
+            Self { avx2: is_x86_feature_detected!("avx2") }
+        }
+
+        /// Every instruction set of the CPU, slowest first, for comparing
+        /// them.
+        pub fn supported() -> Vec<Self> {
+            let avx2 = Some(Self { avx2: true }).filter(|_| is_x86_feature_detected!("avx2"));
+            std::iter::once(Self { avx2: false }).chain(avx2).collect()
+        }
+
+        pub fn name(self) -> &'static str {
+            if self.avx2 { "avx2" } else { "sse2" }
+        }
+
+        /// Takes the first of every 4 bytes of `src` into `dst` for as
+        /// many whole vectors as fit, returning the pixels written.
+        pub(super) fn grayscale4(self, src: &[u8], dst: &mut [u8]) -> usize {
+            unsafe { grayscale4_sse2(src, dst) }
+        }
+
+        /// Writes `out[x]` like [`crate::program_cpu::edge_pixel`] for
+        /// pixels of `row` with a pixel on both sides, starting at `x = 1`,
+        /// for as many whole vectors as fit. Returns the pixels written.
+        pub(super) fn edge_row(self, above: &[u8], row: &[u8], below: &[u8], out: &mut [u8]) -> usize {
+            if self.avx2 {
+                unsafe { edge_row_avx2(above, row, below, out) }
+            } else {
+                unsafe { edge_row_sse2(above, row, below, out) }
+            }
+        }
+
+        /// The first index where `a` or `b` has an edge pixel (`> 192`).
+        pub(super) fn first_edge(self, a: &[u8], b: &[u8]) -> Option<usize> {
+            if self.avx2 {
+                unsafe { first_edge_avx2(a, b) }
+            } else {
+                unsafe { first_edge_sse2(a, b) }
+            }
+        }
+    }
+
+    unsafe fn grayscale4_sse2(src: &[u8], dst: &mut [u8]) -> usize {
+        let len = dst.len().min(src.len() / 4) / 16 * 16;
+        let first_channel = _mm_set1_epi32(0xff);
+
+        for i in (0..len).step_by(16) {
+            let load = |j: usize|
+                _mm_and_si128(
+                    _mm_loadu_si128(src.as_ptr().add(i * 4 + j * 16) as *const __m128i),
+                    first_channel);
+
+            let low = _mm_packs_epi32(load(0), load(1));
+            let high = _mm_packs_epi32(load(2), load(3));
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, _mm_packus_epi16(low, high));
+        }
+
+        len
+    }
+
+    unsafe fn edge_row_sse2(above: &[u8], row: &[u8], below: &[u8], out: &mut [u8]) -> usize {
+        let len = row.len().saturating_sub(2) / 16 * 16;
+        let zero = _mm_setzero_si128();
+
+        // 0xff where the pixel is inside the shape (>= 128).
+        let inside = |line: &[u8], x: usize|
+            _mm_cmplt_epi8(_mm_loadu_si128(line.as_ptr().add(x) as *const __m128i), zero);
+
+        for x in (1 .. 1 + len).step_by(16) {
+            let center = inside(row, x);
+            let neighbours =
+                _mm_and_si128(
+                    _mm_and_si128(
+                        _mm_and_si128(inside(above, x - 1), inside(above, x)),
+                        _mm_and_si128(inside(above, x + 1), inside(row, x - 1))),
+                    _mm_and_si128(
+                        _mm_and_si128(inside(row, x + 1), inside(below, x - 1)),
+                        _mm_and_si128(inside(below, x), inside(below, x + 1))));
+
+            let is_edge = _mm_andnot_si128(neighbours, center);
+            let result = _mm_or_si128(_mm_and_si128(center, _mm_set1_epi8(127)), is_edge);
+            _mm_storeu_si128(out.as_mut_ptr().add(x) as *mut __m128i, result);
+        }
+
+        len
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn edge_row_avx2(above: &[u8], row: &[u8], below: &[u8], out: &mut [u8]) -> usize {
+        let len = row.len().saturating_sub(2) / 32 * 32;
+        let zero = _mm256_setzero_si256();
+
+        for x in (1 .. 1 + len).step_by(32) {
+            macro_rules! inside {
+                ($line: expr, $x: expr) => {
+                    _mm256_cmpgt_epi8(
+                        zero,
+                        _mm256_loadu_si256($line.as_ptr().add($x) as *const __m256i))
+                };
+            }
+
+            let center = inside!(row, x);
+            let neighbours =
+                _mm256_and_si256(
+                    _mm256_and_si256(
+                        _mm256_and_si256(inside!(above, x - 1), inside!(above, x)),
+                        _mm256_and_si256(inside!(above, x + 1), inside!(row, x - 1))),
+                    _mm256_and_si256(
+                        _mm256_and_si256(inside!(row, x + 1), inside!(below, x - 1)),
+                        _mm256_and_si256(inside!(below, x), inside!(below, x + 1))));
+
+            let is_edge = _mm256_andnot_si256(neighbours, center);
+            let result = _mm256_or_si256(_mm256_and_si256(center, _mm256_set1_epi8(127)), is_edge);
+            _mm256_storeu_si256(out.as_mut_ptr().add(x) as *mut __m256i, result);
+        }
+
+        len + edge_row_sse2(&above[len ..], &row[len ..], &below[len ..], &mut out[len ..])
+    }
+
+    unsafe fn first_edge_sse2(a: &[u8], b: &[u8]) -> Option<usize> {
+        let len = a.len().min(b.len());
+        let threshold = _mm_set1_epi8(193u8 as i8);
+
+        // 0xff where the pixel is at least 193.
+        let is_edge = |line: &[u8], i: usize| {
+            let v = _mm_loadu_si128(line.as_ptr().add(i) as *const __m128i);
+            _mm_cmpeq_epi8(_mm_max_epu8(v, threshold), v)
+        };
+
+        let mut i = 0;
+        while i + 16 <= len {
+            let mask = _mm_movemask_epi8(_mm_or_si128(is_edge(a, i), is_edge(b, i)));
+            if mask != 0 {
+                return Some(i + mask.trailing_zeros() as usize);
+            }
+
+            i += 16;
+        }
+
+        (i .. len).find(|&i| a[i] > 192 || b[i] > 192)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn first_edge_avx2(a: &[u8], b: &[u8]) -> Option<usize> {
+        let len = a.len().min(b.len());
+        let threshold = _mm256_set1_epi8(193u8 as i8);
+
+        let mut i = 0;
+        while i + 32 <= len {
+            macro_rules! is_edge {
+                ($line: expr) => {{
+                    let v = _mm256_loadu_si256($line.as_ptr().add(i) as *const __m256i);
+                    _mm256_cmpeq_epi8(_mm256_max_epu8(v, threshold), v)
+                }};
+            }
+
+            let mask = _mm256_movemask_epi8(_mm256_or_si256(is_edge!(a), is_edge!(b)));
+            if mask != 0 {
+                return Some(i + mask.trailing_zeros() as usize);
+            }
+
+            i += 32;
+        }
+
+        first_edge_sse2(&a[i ..], &b[i ..]).map(|j| i + j)
+    }
+}
+
+/// NEON, which every AArch64 CPU has.
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use std::arch::aarch64::*;
+
+    /// See the x86-64 version.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Simd;
+
+    impl Simd {
+        pub fn detect() -> Self {
+            Simd
+        }
+
+        pub fn supported() -> Vec<Self> {
+            vec![Simd]
+        }
+
+        pub fn name(self) -> &'static str {
+            "neon"
+        }
+
+        /// See the x86-64 version.
+        pub(super) fn grayscale4(self, src: &[u8], dst: &mut [u8]) -> usize {
+            let len = dst.len().min(src.len() / 4) / 16 * 16;
+
+            for i in (0..len).step_by(16) {
+                unsafe {
+                    let channels = vld4q_u8(src.as_ptr().add(i * 4));
+                    vst1q_u8(dst.as_mut_ptr().add(i), channels.0);
+                }
+            }
+
+            len
+        }
+
+        /// See the x86-64 version.
+        pub(super) fn edge_row(self, above: &[u8], row: &[u8], below: &[u8], out: &mut [u8]) -> usize {
+            unsafe { edge_row_neon(above, row, below, out) }
+        }
+
+        /// See the x86-64 version.
+        pub(super) fn first_edge(self, a: &[u8], b: &[u8]) -> Option<usize> {
+            unsafe { first_edge_neon(a, b) }
+        }
+    }
+
+    unsafe fn edge_row_neon(above: &[u8], row: &[u8], below: &[u8], out: &mut [u8]) -> usize {
+        let len = row.len().saturating_sub(2) / 16 * 16;
+
+        // 0xff where the pixel is inside the shape (>= 128).
+        let inside = |line: &[u8], x: usize|
+            vcgeq_u8(vld1q_u8(line.as_ptr().add(x)), vdupq_n_u8(128));
+
+        for x in (1 .. 1 + len).step_by(16) {
+            let center = inside(row, x);
+            let neighbours =
+                vandq_u8(
+                    vandq_u8(
+                        vandq_u8(inside(above, x - 1), inside(above, x)),
+                        vandq_u8(inside(above, x + 1), inside(row, x - 1))),
+                    vandq_u8(
+                        vandq_u8(inside(row, x + 1), inside(below, x - 1)),
+                        vandq_u8(inside(below, x), inside(below, x + 1))));
+
+            let is_edge = vbicq_u8(center, neighbours);
+            let result = vorrq_u8(vandq_u8(center, vdupq_n_u8(127)), is_edge);
+            vst1q_u8(out.as_mut_ptr().add(x), result);
+        }
+
+        len
+    }
+
+    unsafe fn first_edge_neon(a: &[u8], b: &[u8]) -> Option<usize> {
+        let len = a.len().min(b.len());
+        let threshold = vdupq_n_u8(192);
+
+        let mut i = 0;
+        while i + 16 <= len {
+            let mask =
+                vorrq_u8(
+                    vcgtq_u8(vld1q_u8(a.as_ptr().add(i)), threshold),
+                    vcgtq_u8(vld1q_u8(b.as_ptr().add(i)), threshold));
+
+            // Narrows every byte of the mask to 4 bits.
+            let bits =
+                vget_lane_u64(
+                    vreinterpret_u64_u8(vshrn_n_u16(vreinterpretq_u16_u8(mask), 4)),
+                    0);
+
+            if bits != 0 {
+                return Some(i + bits.trailing_zeros() as usize / 4);
+            }
+
+            i += 16;
+        }
+
+        (i .. len).find(|&i| a[i] > 192 || b[i] > 192)
+    }
+}
+
+/// Scalar fallback for other architectures.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod arch {
+    /// See the x86-64 version, the kernels use no SIMD.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Simd;
+
+    impl Simd {
+        pub fn detect() -> Self {
+            Simd
+        }
+
+        pub fn supported() -> Vec<Self> {
+            Vec::new()
+        }
+
+        pub fn name(self) -> &'static str {
+            "scalar"
+        }
+
+        pub(super) fn grayscale4(self, _: &[u8], _: &mut [u8]) -> usize {
+            0
+        }
+
+        pub(super) fn edge_row(self, _: &[u8], _: &[u8], _: &[u8], _: &mut [u8]) -> usize {
+            0
+        }
+
+        pub(super) fn first_edge(self, a: &[u8], b: &[u8]) -> Option<usize> {
+            (0 .. a.len().min(b.len())).find(|&i| a[i] > 192 || b[i] > 192)
+        }
+    }
+}
+
+#[test]
+fn test_simd_matches_scalar() {
+    let (width, height) = (83, 61);
+    let mut seed = 1u32;
+    let mut random = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as u8
+    };
+
+    let rgba: Vec<u8> = (0 .. width * height * 4).map(|_| random()).collect();
+    let mut image = vec![0; width * height];
+    rgba_to_grayscale(&rgba, &mut image, 4);
+    assert!(image.iter().enumerate().all(|(i, &x)| x == rgba[i * 4]));
+
+    // Random blobs with noise at the edges.
+    for (i, x) in image.iter_mut().enumerate() {
+        let (dx, dy) = ((i % width) as i32 % 29 - 14, (i / width) as i32 % 23 - 11);
+        *x = if dx * dx + dy * dy < 80 || *x > 250 { 255 } else { 0 };
+    }
+
+    let mut edge = vec![0; width * height];
+    let mut expected_edge = vec![0; width * height];
+    edge_detect(&image, &mut edge, width, height, 2);
+    program_cpu::edge_detect(&image, &mut expected_edge, width, height, 1);
+    assert_eq!(edge, expected_edge);
+
+    for &(stride, search_radius) in &[(1, 40), (3, 12)] {
+        let (sdf_width, sdf_height) = (width / stride, height / stride);
+        let mut sdf = vec![0.0; sdf_width * sdf_height];
+        let mut expected_sdf = vec![0.0; sdf_width * sdf_height];
+
+        sdf_generate(
+            &edge, &mut sdf, width, height, sdf_width, sdf_height, stride, search_radius, 3);
+        program_cpu::sdf_generate(
+            &edge, &mut expected_sdf, width, height, sdf_width, sdf_height, stride, search_radius, 1);
+
+        assert_eq!(sdf, expected_sdf);
+    }
+
+    // Every instruction set of the CPU, not only the fastest one.
+    let (sdf_width, sdf_height) = (width / 3, height / 3);
+    let mut expected_sdf = vec![0.0; sdf_width * sdf_height];
+    program_cpu::sdf_generate(
+        &edge, &mut expected_sdf, width, height, sdf_width, sdf_height, 3, 12, 1);
+
+    for simd in Simd::supported() {
+        let mut gray = vec![0; width * height];
+        simd.rgba_to_grayscale(&rgba, &mut gray, 4);
+        assert!(gray.iter().enumerate().all(|(i, &x)| x == rgba[i * 4]), "{}", simd.name());
+
+        simd.edge_detect(&image, &mut edge, width, height, 2);
+        assert_eq!(edge, expected_edge, "{}", simd.name());
+
+        let mut sdf = vec![0.0; sdf_width * sdf_height];
+        simd.sdf_generate(&edge, &mut sdf, width, height, sdf_width, sdf_height, 3, 12, 3);
+        assert_eq!(sdf, expected_sdf, "{}", simd.name());
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::backend::{SdfBackend, SdfOptions};
+use crate::error::{Error, Result};
+use crate::mono_image::{MonoImage, SampleFormat};
+
+/// Reads a PNG file a few rows at a time as grayscale pixels, so the whole
+/// image never has to be in memory.
+///
+/// Interlaced files store the image in passes, they are decoded at once
+/// and then handed out row by row.
+pub struct PngRowReader {
+    reader: png::Reader<BufReader<File>>,
+    interlaced: Option<Vec<u8>>,
+    channels: usize,
+    row: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl PngRowReader {
+    pub fn open(png: &str) -> Result<Self> {
+        let mut reader = png::Decoder::new(BufReader::new(File::open(png)?)).read_info()?;
+        let (color_type, bit_depth) = reader.output_color_type();
+
+        if bit_depth != png::BitDepth::Eight {
+            return Err(Error::UnsupportedColorType(color_type, bit_depth));
+        }
+
+        let channels =
+            match color_type {
+                | png::ColorType::Grayscale => 1,
+                | png::ColorType::GrayscaleAlpha => 2,
+                | png::ColorType::Rgb => 3,
+                | png::ColorType::Rgba => 4,
+                | color_type =>
+                    return Err(Error::UnsupportedColorType(color_type, bit_depth))
+            };
+
+        let interlaced =
+            if reader.info().interlaced {
+                let mut buf = vec![0; reader.output_buffer_size()];
+                reader.next_frame(&mut buf)?;
+                Some(buf)
+            } else {
+                None
+            };
+
+        let (width, height) = reader.info().size();
+
+        Ok(Self {
+            reader,
+            interlaced,
+            channels,
+            row: 0,
+            width: width as usize,
+            height: height as usize
+        })
+    }
+
+    /// Appends the next `rows` rows of the file to the bottom of `to`,
+    /// converted to grayscale with `backend`.
+    pub fn read_rows(
+        &mut self,
+        to: &mut MonoImage,
+        rows: usize,
+        backend: &mut dyn SdfBackend)
+        -> Result<()>
+    {
+        assert!(to.width == self.width && to.channels == 1);
+        assert!(self.row + rows <= self.height);
+
+        let row_size = self.width * self.channels;
+        let mut buf = Vec::with_capacity(rows * row_size);
+
+        for _ in 0..rows {
+            match &self.interlaced {
+                Some(image) =>
+                    buf.extend_from_slice(&image[self.row * row_size .. (self.row + 1) * row_size]),
+                None =>
+                    match self.reader.next_row()? {
+                        Some(row) => buf.extend_from_slice(&row.data()[..row_size]),
+                        None => break
+                    }
+            }
+
+            self.row += 1;
+        }
+
+        let mut band = MonoImage::new(self.width, buf.len() / row_size);
+
+        if self.channels == 1 {
+            band.pixels = buf;
+        } else {
+            backend.grayscale(&buf, self.channels, &mut band)?;
+        }
+
+        to.pixels.extend_from_slice(&band.pixels);
+        to.height += band.height;
+        Ok(())
+    }
+}
+
+enum Output {
+    Png(Box<png::StreamWriter<'static, BufWriter<File>>>),
+    Pfm { file: BufWriter<File>, header: u64 }
+}
+
+/// Encodes distances and writes them to a PNG or PFM file a few rows at a
+/// time, from top to bottom, like [`MonoImage::save_distances`].
+pub struct DistanceWriter {
+    output: Output,
+    format: SampleFormat,
+    row: usize,
+    width: usize,
+    height: usize
+}
+
+impl DistanceWriter {
+    /// Creates `out` for `width` x `height` grayscale distances saved as
+    /// `format`.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the extension of `out` is not
+    /// the one of `format`, or if the image would be empty.
+    pub fn create(
+        out: &Path,
+        width: usize,
+        height: usize,
+        format: SampleFormat)
+        -> Result<Self>
+    {
+        let extension = out.extension().and_then(|x| x.to_str()).unwrap_or("");
+        if !extension.eq_ignore_ascii_case(format.extension()) {
+            return Err(Error::InvalidArgument(
+                format!(
+                    "{}-bit samples are saved as .{} files, not {}",
+                    format,
+                    format.extension(),
+                    out.display())));
+        }
+
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidArgument(
+                format!("Can not save the empty {}x{} image {}", width, height, out.display())));
+        }
+
+        let depth =
+            match format {
+                SampleFormat::U8 => png::BitDepth::Eight,
+                SampleFormat::U16 => png::BitDepth::Sixteen,
+                SampleFormat::F32 => {
+                    let mut file = BufWriter::new(File::create(out)?);
+
+                    // A negative scale marks little endian samples, rows go
+                    // bottom to top, so they are written at their offset.
+                    write!(file, "Pf\n{} {}\n-1.0\n", width, height)?;
+                    let header = file.stream_position()?;
+                    file.get_ref().set_len(header + (width * height * 4) as u64)?;
+
+                    return Ok(Self {
+                        output: Output::Pfm { file, header },
+                        format,
+                        row: 0,
+                        width,
+                        height
+                    });
+                }
+            };
+
+        let mut enc =
+            png::Encoder::new(BufWriter::new(File::create(out)?), width as u32, height as u32);
+
+        enc.set_color(png::ColorType::Grayscale);
+        enc.set_depth(depth);
+
+        Ok(Self {
+            output: Output::Png(Box::new(enc.write_header()?.into_stream_writer()?)),
+            format,
+            row: 0,
+            width,
+            height
+        })
+    }
+
+    /// Encodes the distances in `rows` with `options.encoding` and writes
+    /// them below the rows written before.
+    pub fn write_rows(&mut self, rows: &MonoImage<f32>, options: &SdfOptions) -> Result<()> {
+        assert!(rows.width == self.width && rows.channels == 1);
+        assert!(self.row + rows.height <= self.height);
+
+        match &mut self.output {
+            Output::Png(w) => {
+                let data: Vec<u8> =
+                    match self.format {
+                        SampleFormat::U16 =>
+                            rows.encode::<u16>(options).pixels
+                                .iter()
+                                .flat_map(|x| x.to_be_bytes())
+                                .collect(),
+                        _ => rows.encode::<u8>(options).pixels
+                    };
+
+                w.write_all(&data)?;
+            },
+            Output::Pfm { file, header } => {
+                let samples = rows.encode::<f32>(options);
+                let row_size = (self.width * 4) as u64;
+
+                for (i, row) in samples.pixels.chunks(self.width.max(1)).enumerate() {
+                    let y = (self.height - 1 - (self.row + i)) as u64;
+                    file.seek(SeekFrom::Start(*header + y * row_size))?;
+
+                    for x in row {
+                        file.write_all(&x.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+
+        self.row += rows.height;
+        Ok(())
+    }
+
+    /// Finishes the file after every row has been written.
+    pub fn finish(self) -> Result<()> {
+        assert!(self.row == self.height);
+
+        match self.output {
+            Output::Png(w) => w.finish()?,
+            Output::Pfm { mut file, .. } => file.flush()?
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_generate_sdf_streamed() {
+    use crate::{CpuBackend, DistanceEncoding, SdfAlgorithm, SdfFilter};
+
+    let options = SdfOptions {
+        stride: 2,
+        search_radius: 6,
+        algorithm: SdfAlgorithm::RingSearch,
+        filter: SdfFilter::Point,
+        anti_aliased: false,
+        encoding: DistanceEncoding::default()
+    };
+
+    let mut image: MonoImage = MonoImage::new(61, 47);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let inside = (x as i32 - 30).pow(2) + (y as i32 - 20).pow(2) < 200 || (x / 9 + y / 7) % 3 == 0;
+            image.set_pixel(x, y, if inside { 255 } else { 0 });
+        }
+    }
+
+    let dir = std::env::temp_dir();
+    let input = dir.join("sdftool-streamed.png");
+    let output = dir.join("sdftool-streamed.pfm");
+    image.save_png(&input).unwrap();
+
+    let mut backend = CpuBackend::new();
+    let expected: MonoImage<f32> = crate::generate_sdf(&mut backend, &image, &options).unwrap();
+
+    for tile_size in [0, 4, 7, 100] {
+        crate::generate_sdf_streamed(
+            &mut backend, 
+            input.to_str().unwrap(), 
+            &output, 
+            SampleFormat::F32, 
+            &options, 
+            tile_size)
+            .unwrap();
+
+        let data = std::fs::read(&output).unwrap();
+        let samples: Vec<f32> = 
+            data[data.len() - expected.pixels.len() * 4 ..]
+                .chunks(4)
+                .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+                .collect();
+
+        // PFM rows go bottom to top.
+        let rows: Vec<&[f32]> = samples.chunks(expected.width).rev().collect();
+        assert_eq!(rows.concat(), expected.pixels);
+    }
+
+    let empty = dir.join("sdftool-streamed-empty.png");
+    let _ = std::fs::remove_file(&empty);
+    let result = DistanceWriter::create(&empty, 0, 3, SampleFormat::U8);
+    assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    assert!(!empty.exists());
+}
@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+/// A multi-producer, multi-consumer queue handing out tasks in batches.
+///
+/// Consumers block in [`WorkQueue::pop_batch`] until tasks arrive or the
+/// queue is closed, so idle workers do not spin, and taking several tasks
+/// per lock keeps the contention low when the tasks are short.
+pub struct WorkQueue<T> {
+    state: Mutex<QueueState<T>>,
+    available: Condvar,
+    batch_size: usize
+}
+
+struct QueueState<T> {
+    tasks: VecDeque<T>,
+    closed: bool
+}
+
+impl<T> WorkQueue<T> {
+    /// Creates an open queue handing out at most `batch_size` tasks at once.
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                tasks: VecDeque::new(),
+                closed: false
+            }),
+            available: Condvar::new(),
+            batch_size: batch_size.max(1)
+        }
+    }
+
+    /// Adds tasks to the queue and wakes up waiting consumers. Tasks pushed
+    /// after [`WorkQueue::close`] are dropped.
+    pub fn extend(&self, tasks: impl IntoIterator<Item = T>) {
+        let mut state = self.state.lock().unwrap();
+        if !state.closed {
+            state.tasks.extend(tasks);
+            self.available.notify_all();
+        }
+    }
+
+    /// Marks the end of the tasks. Consumers take the remaining tasks, then
+    /// [`WorkQueue::pop_batch`] returns `false`.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.available.notify_all();
+    }
+
+    /// Drops the remaining tasks and closes the queue, stopping all
+    /// consumers after their current batch.
+    pub fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.tasks.clear();
+        state.closed = true;
+        self.available.notify_all();
+    }
+
+    /// Waits for tasks and moves up to the batch size of them into `batch`.
+    /// Returns `false` once the queue is closed and empty.
+    ///
+    /// The batch shrinks as the queue drains, leaving tasks for the other
+    /// consumers at the end.
+    pub fn pop_batch(&self, batch: &mut Vec<T>) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        while state.tasks.is_empty() {
+            if state.closed {
+                return false;
+            }
+
+            state = self.available.wait(state).unwrap();
+        }
+
+        let len = state.tasks.len();
+        let count = (len / 4).clamp(1, self.batch_size);
+        batch.extend(state.tasks.drain(.. count));
+        true
+    }
+}
+
+#[test]
+fn test_work_queue() {
+    use std::sync::Arc;
+
+    let queue = Arc::new(WorkQueue::new(8));
+
+    let consumers: Vec<_> =
+        (0..4)
+            .map(|_| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    let (mut batch, mut sum) = (Vec::new(), 0);
+                    while queue.pop_batch(&mut batch) {
+                        assert!(!batch.is_empty() && batch.len() <= 8);
+                        sum += batch.drain(..).sum::<u64>();
+                    }
+                    sum
+                })
+            })
+            .collect();
+
+    let producers: Vec<_> =
+        (0..2)
+            .map(|i| {
+                let queue = queue.clone();
+                std::thread::spawn(move ||
+                    for j in 0..100 {
+                        queue.extend(vec![i * 1000 + j; 3]);
+                    })
+            })
+            .collect();
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+
+    queue.close();
+
+    let sum: u64 = consumers.into_iter().map(|x| x.join().unwrap()).sum();
+    assert_eq!(sum, (0..100).map(|j| 3 * (j + 1000 + j)).sum());
+}